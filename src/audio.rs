@@ -65,6 +65,20 @@ impl<A> Audio for Range<A>
     }
 }
 
+// Allows tests to build `Map`s and `Sampler`s without loading any audio.
+#[cfg(test)]
+impl Audio for () {
+    type Frame = [f32; 2];
+    fn data(&self) -> &[Self::Frame] { &[] }
+}
+
+// Allows tests to play audio of a known length.
+#[cfg(test)]
+impl Audio for Vec<[f32; 2]> {
+    type Frame = [f32; 2];
+    fn data(&self) -> &[Self::Frame] { self }
+}
+
 
 #[cfg(feature="wav")]
 pub mod wav {
@@ -120,8 +134,6 @@ pub mod wav {
         pub fn from_file<P>(path: P, target_sample_hz: f64) -> Result<Self, Error>
             where P: AsRef<std::path::Path>,
         {
-            use sample::{Frame, Sample, Signal};
-
             let path = path.as_ref();
             let mut wav_reader = try!(hound::WavReader::open(path));
 
@@ -171,75 +183,148 @@ pub mod wav {
                 },
             }
 
-            let boxed_samples = samples.into_boxed_slice();
-            let boxed_frames: Box<[F]> = match (spec.channels, F::n_channels() as u16) {
+            let source_hz = spec.sample_rate as f64;
+            let data = match super::pcm::to_frames(samples, spec.channels as usize, source_hz, target_sample_hz) {
+                Some(data) => data,
+                None => return Err(Error::UnsupportedChannelMapping(spec.channels, F::n_channels() as u16)),
+            };
 
-                // In the case that the `spec` has a different number of channels to the actual
-                // slice, just collect as many valid frames as we can and discard the final
-                // mismatching frame.
-                (source, target) if source == target => {
-                    let samples = boxed_samples.iter().cloned();
-                    let vec: Vec<F> = sample::signal::from_interleaved_samples(samples)
-                        .collect();
-                    vec.into_boxed_slice()
-                },
+            Ok(Audio {
+                path: path.to_path_buf(),
+                sample_hz: target_sample_hz,
+                data: data,
+            })
+        }
 
-                // Sum the left and right channels together when mapping to a mono signal.
-                (2, 1) => {
-                    let samples = boxed_samples.iter().cloned();
-                    let vec: Vec<F> = 
-                        sample::signal::from_interleaved_samples::<_, [F::Sample; 2]>(samples)
-                            .filter_map(|f| {
-                                let mut channels = f.channels();
-                                channels.next()
-                                    .and_then(|l| channels.next().map(|r| (l, r)))
-                                    .map(|(l, r)| {
-                                        let sum = l.add_amp(r.to_signed_sample());
-                                        F::from_fn(|_| sum)
-                                    })
-                            })
-                            .collect();
-                    vec.into_boxed_slice()
-                },
+    }
 
-                // Simply copy the single mono channel to both channels in the output stereo
-                // signal.
-                (1, 2) => {
-                    let samples = boxed_samples.iter().cloned();
-                    let vec: Vec<F> = samples.map(|s| F::from_fn(|_| s)).collect();
-                    vec.into_boxed_slice()
-                },
+    impl From<hound::Error> for Error {
+        fn from(err: hound::Error) -> Self {
+            Error::Hound(err)
+        }
+    }
 
-                (source, target) => {
-                    return Err(Error::UnsupportedChannelMapping(source, target))
-                },
-                
+    impl std::error::Error for Error {
+        fn description(&self) -> &str {
+            match *self {
+                Error::Hound(ref hound) => std::error::Error::description(hound),
+                Error::UnsupportedBitsPerSample(_n_bits) => "unsupported bits per sample",
+                Error::UnsupportedChannelMapping(_source, _target) => "unsupported channel mapping",
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+            std::fmt::Debug::fmt(self, f)
+        }
+    }
+
+}
+
+
+#[cfg(feature="flac")]
+pub mod flac {
+    use claxon;
+    use sample;
+    use std;
+
+
+    /// FLAC data decoded into memory as a single contiguous slice of PCM frames.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Audio<F> {
+        pub path: std::path::PathBuf,
+        pub data: Box<[F]>,
+        pub sample_hz: f64,
+    }
+
+    /// Errors that may occur during `FLAC` loading
+    #[derive(Debug)]
+    pub enum Error {
+        /// Some error returned from the `claxon` crate during decoding.
+        Claxon(claxon::Error),
+        /// The bit depth of the given FLAC file is unsupported.
+        UnsupportedBitsPerSample(u16),
+        /// There is no obvious way to map the given channels described in the FLAC stream info to
+        /// the number of channels in the `Frame` type.
+        ///
+        /// Contains the source number of channels and the target number of channels.
+        UnsupportedChannelMapping(u16, u16),
+    }
+
+
+    impl<F> super::Audio for Audio<F>
+        where F: sample::Frame,
+    {
+        type Frame = F;
+        fn data(&self) -> &[Self::Frame] {
+            &self.data[..]
+        }
+    }
+
+    impl<F> Audio<F>
+        where F: sample::Frame,
+              F::Sample: sample::Duplex<f64> + sample::Duplex<i32>,
+              Box<[F::Sample]>: sample::ToBoxedFrameSlice<F>,
+    {
+
+        /// Loads a `Sample` from the `.flac` file at the given `path`.
+        ///
+        /// As with `wav::Audio::from_file`, the PCM data decoded from the file will be:
+        /// - re-sized from its source bit rate to that of the target and
+        /// - re-sampled upon loading (rather than at playback) to the given target sample rate for
+        /// efficiency.
+        pub fn from_file<P>(path: P, target_sample_hz: f64) -> Result<Self, Error>
+            where P: AsRef<std::path::Path>,
+        {
+            let path = path.as_ref();
+            let mut flac_reader = try!(claxon::FlacReader::open(path));
+
+            let info = flac_reader.streaminfo();
+
+            // FLAC samples are decoded to the least significant bits of an `i32`, so shift them
+            // up to the full range of an `i32` before converting them to the target type.
+            let shift = match info.bits_per_sample {
+                n if n >= 1 && n <= 32 => 32 - n,
+                n => return Err(Error::UnsupportedBitsPerSample(n as u16)),
             };
 
-            // Convert the sample rate to our target sample rate.
-            let frames: Vec<F> = boxed_frames.iter().cloned()
-                .from_hz_to_hz(spec.sample_rate as f64, target_sample_hz)
-                .collect();
+            // Collect the samples in a loop so that we may handle any errors if necessary.
+            let mut samples: Vec<F::Sample> = Vec::new();
+            for sample in flac_reader.samples() {
+                let read_sample: i32 = try!(sample);
+                let i32_sample = read_sample << shift;
+                samples.push(sample::Sample::to_sample(i32_sample));
+            }
+
+            let source_hz = info.sample_rate as f64;
+            let data = match super::pcm::to_frames(samples, info.channels as usize, source_hz, target_sample_hz) {
+                Some(data) => data,
+                None => {
+                    let channels = info.channels as u16;
+                    return Err(Error::UnsupportedChannelMapping(channels, F::n_channels() as u16));
+                },
+            };
 
             Ok(Audio {
                 path: path.to_path_buf(),
                 sample_hz: target_sample_hz,
-                data: frames.into_boxed_slice(),
+                data: data,
             })
         }
 
     }
 
-    impl From<hound::Error> for Error {
-        fn from(err: hound::Error) -> Self {
-            Error::Hound(err)
+    impl From<claxon::Error> for Error {
+        fn from(err: claxon::Error) -> Self {
+            Error::Claxon(err)
         }
     }
 
     impl std::error::Error for Error {
         fn description(&self) -> &str {
             match *self {
-                Error::Hound(ref hound) => std::error::Error::description(hound),
+                Error::Claxon(ref claxon) => std::error::Error::description(claxon),
                 Error::UnsupportedBitsPerSample(_n_bits) => "unsupported bits per sample",
                 Error::UnsupportedChannelMapping(_source, _target) => "unsupported channel mapping",
             }
@@ -252,4 +337,108 @@ pub mod wav {
         }
     }
 
+
+    #[test]
+    fn test() {
+        extern crate find_folder;
+
+        // A 24-bit stereo ramp whose right channel is the inverse of its left.
+        const RAMP: &'static str = "ramp C4.flac";
+        const SAMPLE_HZ: f64 = 44_100.0;
+
+        let assets = find_folder::Search::ParentsThenKids(5, 5).for_folder("assets").unwrap();
+        let path = assets.join(RAMP);
+        let left = |i: usize| (i as i32 - 128) << 16;
+
+        // Samples are shifted up from 24 bits to the full range of the target sample type.
+        let audio = Audio::<[i32; 2]>::from_file(&path, SAMPLE_HZ).unwrap();
+        assert_eq!(audio.data.len(), 256);
+        for (i, &frame) in audio.data.iter().enumerate() {
+            assert_eq!(frame, [left(i) << 8, (-left(i) - 1) << 8]);
+        }
+        let audio = Audio::<[i16; 2]>::from_file(&path, SAMPLE_HZ).unwrap();
+        for (i, &frame) in audio.data.iter().enumerate() {
+            assert_eq!(frame, [(left(i) >> 8) as i16, ((-left(i) - 1) >> 8) as i16]);
+        }
+
+        // Both channels are summed when loaded as mono.
+        let audio = Audio::<[i32; 1]>::from_file(&path, SAMPLE_HZ).unwrap();
+        assert!(audio.data.iter().all(|&frame| frame == [-1 << 8]));
+    }
+
+}
+
+
+/// Conversion of the interleaved PCM samples read from audio files to frames at the target
+/// sample rate, shared by each of the file formats.
+#[cfg(any(feature="wav", feature="flac"))]
+mod pcm {
+    use sample;
+
+
+    /// Maps the given interleaved `samples` of audio with the given number of `channels` to
+    /// frames of type `F`, re-sampling them from the `source_hz` to the `target_hz`.
+    ///
+    /// Returns `None` if there is no obvious way to map the source channels to those of `F`.
+    pub fn to_frames<F>(samples: Vec<F::Sample>,
+                        channels: usize,
+                        source_hz: f64,
+                        target_hz: f64) -> Option<Box<[F]>>
+        where F: sample::Frame,
+              F::Sample: sample::Duplex<f64>,
+              Box<[F::Sample]>: sample::ToBoxedFrameSlice<F>,
+    {
+        use sample::{Frame, Sample, Signal};
+
+        let boxed_samples = samples.into_boxed_slice();
+        let boxed_frames: Box<[F]> = match (channels, F::n_channels()) {
+
+            // In the case that the source has a different number of channels to the actual
+            // slice, just collect as many valid frames as we can and discard the final
+            // mismatching frame.
+            (source, target) if source == target => {
+                let samples = boxed_samples.iter().cloned();
+                let vec: Vec<F> = sample::signal::from_interleaved_samples(samples)
+                    .collect();
+                vec.into_boxed_slice()
+            },
+
+            // Sum the left and right channels together when mapping to a mono signal.
+            (2, 1) => {
+                let samples = boxed_samples.iter().cloned();
+                let vec: Vec<F> = 
+                    sample::signal::from_interleaved_samples::<_, [F::Sample; 2]>(samples)
+                        .filter_map(|f| {
+                            let mut channels = f.channels();
+                            channels.next()
+                                .and_then(|l| channels.next().map(|r| (l, r)))
+                                .map(|(l, r)| {
+                                    let sum = l.add_amp(r.to_signed_sample());
+                                    F::from_fn(|_| sum)
+                                })
+                        })
+                        .collect();
+                vec.into_boxed_slice()
+            },
+
+            // Simply copy the single mono channel to both channels in the output stereo
+            // signal.
+            (1, 2) => {
+                let samples = boxed_samples.iter().cloned();
+                let vec: Vec<F> = samples.map(|s| F::from_fn(|_| s)).collect();
+                vec.into_boxed_slice()
+            },
+
+            _ => return None,
+
+        };
+
+        // Convert the sample rate to our target sample rate.
+        let frames: Vec<F> = boxed_frames.iter().cloned()
+            .from_hz_to_hz(source_hz, target_hz)
+            .collect();
+
+        Some(frames.into_boxed_slice())
+    }
+
 }
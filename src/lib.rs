@@ -1,7 +1,9 @@
+#[cfg(feature="flac")] extern crate claxon;
 #[cfg(feature="wav")] extern crate hound;
 pub extern crate instrument;
 pub extern crate sample;
 extern crate pitch_calc as pitch;
+extern crate rand;
 extern crate time_calc as time;
 
 pub use audio::Audio;
@@ -13,7 +15,10 @@ pub mod audio;
 pub mod dynamic;
 pub mod map;
 mod mode;
+pub mod naming;
+pub mod note;
 mod sampler;
+pub mod velocity;
 
 #[cfg(feature="serde_serialization")]
 mod serde;
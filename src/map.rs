@@ -1,12 +1,82 @@
 use {Step, Velocity, MIN_STEP, MAX_STEP};
 use audio::Audio;
 use pitch;
+use rand;
+use std;
+use time;
 
 
 /// A type that maps frequncy and velocity ranges to audio samples.
-#[derive(Clone, Debug, PartialEq)]
+///
+/// Two maps are equal if their zones, key switches, choke fade, articulation and controller values
+/// are equal.
+#[derive(Clone, Debug)]
 pub struct Map<A> {
+    /// The zones of the map in order of priority.
+    ///
+    /// If the `pairs` are modified directly, `Map::reindex` must be called afterwards, as until then
+    /// lookups may find the wrong zone or none at all. Each zone keeps its `id` as it is moved
+    /// within the `pairs`.
     pub pairs: Vec<SampleOverRange<A>>,
+    /// The id given to the next zone added to the map.
+    next_id: u64,
+    /// The range of steps whose `note_on`s switch between articulations rather than sounding.
+    ///
+    /// The lowest step selects articulation `0`, the next articulation `1` and so on.
+    pub key_switches: Option<Range<Step>>,
+    /// The duration over which a playing note is faded out when it is choked by a `note_on` within
+    /// the same `choke_group`.
+    pub choke_fade: time::Ms,
+    /// The articulation and controller values that determine which zones are currently played.
+    selection: Selection,
+    /// An index for each articulation referred to by the zones, followed by an index shared by
+    /// all other articulations, so that switching articulation requires no re-indexing.
+    indices: Vec<Index>,
+}
+
+/// A stable handle to a zone within a `Map`.
+///
+/// Unlike the index of a zone within the `pairs`, a `ZoneId` continues to refer to the same zone
+/// as other zones are inserted and removed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ZoneId(u64);
+
+/// An iterator yielding each zone of a `Map` in order of priority, along with its `ZoneId`.
+pub struct Iter<'a, A: 'a> {
+    pairs: std::slice::Iter<'a, SampleOverRange<A>>,
+}
+
+/// The default `Map::choke_fade`, short enough to cut a note off without clicking.
+pub const DEFAULT_CHOKE_FADE_MS: f64 = 10.0;
+
+/// The maximum number of samples that may be layered to play a single note.
+///
+/// Crossfading zones beyond this number are not played.
+pub const MAX_LAYERS: usize = 8;
+
+/// Refers to a single `Sample` within a `Map`.
+///
+/// The index of the zone is only valid until the `pairs` are next modified, after which the
+/// `SampleIdx` may be brought up to date with `Map::locate`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SampleIdx {
+    /// The index of the zone within the `Map`'s `pairs`.
+    pub zone: usize,
+    /// The id of the zone, used to check that the zone is still found at the `zone` index.
+    pub id: Option<ZoneId>,
+    /// The index of the sample within the zone's group, as used by `SampleOverRange::group_sample`.
+    pub sample: usize,
+}
+
+/// The samples layered to play a single note, each paired with the gain at which it should be
+/// played.
+///
+/// Samples are referred to by index and stored inline so that looking up a note requires no
+/// allocation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Layers {
+    len: usize,
+    layers: [(SampleIdx, f32); MAX_LAYERS],
 }
 
 /// A performable `Sample` with some base playback Hz and Velocity.
@@ -15,6 +85,29 @@ pub struct Sample<A> {
     pub base_hz: pitch::Hz,
     pub base_vel: Velocity,
     pub audio: A,
+    /// How closely the playback pitch follows the pitch of the note, where `1.0` (100%) repitches
+    /// the sample by the ratio between the note's hz and the `base_hz`.
+    ///
+    /// `0.0` always plays the sample at its original pitch, which is useful for drum hits and
+    /// effects that are mapped across many steps. Values above `1.0` exaggerate the interval.
+    pub key_tracking: f32,
+    /// If `true`, the sample ignores `note_off` and always plays through to the end of its audio,
+    /// as is common for drum hits and effects.
+    pub one_shot: bool,
+    /// An offset to the playback pitch in cents, used to fine-tune the sample.
+    pub tune_cents: f32,
+    /// A gain in decibels applied to the sample's amplitude, used to match levels between samples.
+    pub gain_db: f32,
+    /// The position of the sample within the stereo field from `-1.0` (hard left) to `1.0` (hard
+    /// right).
+    ///
+    /// Panning attenuates the opposite channel, leaving the sample unchanged at `0.0`. It has no
+    /// effect unless the `Audio` has exactly two channels.
+    pub pan: f32,
+    /// If `Some`, the region of the audio that repeats for as long as the note is held.
+    ///
+    /// Ignored by `one_shot` samples, which always play through to the end of their audio.
+    pub sustain_loop: Option<Loop>,
 }
 
 /// A 2-dimensional space, represented as a frequency range and a velocity range.
@@ -29,6 +122,237 @@ pub struct StepVelRange {
 pub struct SampleOverRange<A> {
     pub range: StepVelRange,
     pub sample: Sample<A>,
+    /// Alternative samples that take turns with `sample` on successive `note_on`s.
+    ///
+    /// If there are no `alternates`, `sample` is played on every `note_on`.
+    pub alternates: Vec<Sample<A>>,
+    /// Determines which sample of the group is played on the next `note_on`.
+    pub alternation: Alternation,
+    /// If `Some`, the zone is blended with the other crossfading zones whose velocity ranges
+    /// overlap its own rather than hiding them.
+    pub vel_crossfade: Option<Crossfade>,
+    /// If `Some`, the zone is blended with the other crossfading zones whose step ranges overlap
+    /// its own according to where the note sits within the overlap.
+    pub step_crossfade: Option<Crossfade>,
+    /// If `Some`, the zone only covers the fractional steps within this range, allowing for
+    /// microtonal zone boundaries.
+    ///
+    /// The range should lie within the `range.step` rounded to the nearest `Step`, as is the case
+    /// when the zone is constructed via `SampleOverRange::fractional`.
+    pub fractional_step: Option<Range<f32>>,
+    /// Whether the zone is played by `note_on` or by `note_off`.
+    pub trigger: Trigger,
+    /// If `Some`, the gain of a `Release` zone halves for every `release_half_life` for which the
+    /// released note was held, i.e. the release of a long note is quieter than that of a short
+    /// one.
+    pub release_half_life: Option<time::Ms>,
+    /// The articulation to which the zone belongs, or `None` if the zone is played regardless of
+    /// the `Map`'s active articulation.
+    pub articulation: Option<usize>,
+    /// The ranges within which the values of the given controllers must lie for the zone to be
+    /// played, i.e. to choose between vibrato and non-vibrato recordings with the mod wheel.
+    ///
+    /// Zones without `controllers` are played regardless of controller values.
+    pub controllers: Vec<(Controller, Range<f32>)>,
+    /// If `Some`, playing the zone chokes (quickly fades out) any note still sounding from a zone
+    /// of the same group, i.e. a closed hi-hat cutting off an open one.
+    ///
+    /// `Mono` modes cut off the playing note upon every `note_on` regardless of its group.
+    pub choke_group: Option<u32>,
+    /// If `Some`, notes played at velocity `0.0` begin this far into the audio, with the offset
+    /// shrinking linearly to the start of the audio at velocity `1.0`, i.e. to skip the soft
+    /// attack that precedes the transient of a hard hit.
+    ///
+    /// Notes that begin beyond the end of the sample's `sustain_loop` play on without looping.
+    pub start_offset: Option<StartOffset>,
+    /// The id given to the zone by the `Map` that it belongs to, or `None` if the zone has not
+    /// been added to a `Map`.
+    ///
+    /// Zones are given new ids when inserted into a `Map`, and `Map::reindex` gives new ids to
+    /// zones added to the `pairs` directly without one.
+    pub id: Option<ZoneId>,
+}
+
+/// A performance control, other than step and velocity, by which zones may be selected.
+///
+/// The values of all controllers are normalised to the range `0.0` to `1.0`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Controller {
+    /// The MIDI control change with the given number, i.e. `1` for the mod wheel or `4` for a foot
+    /// controller.
+    Cc(u8),
+    /// Channel aftertouch.
+    Aftertouch,
+    /// A user-defined parameter, i.e. a layer switch on an instrument's interface.
+    Param(u32),
+}
+
+/// The state of the performance, other than step and velocity, that determines which zones are
+/// played.
+#[derive(Clone, Debug, PartialEq)]
+struct Selection {
+    /// The active articulation.
+    articulation: usize,
+    /// The current value of each controller that has been set.
+    controllers: Vec<(Controller, f32)>,
+}
+
+/// Determines the order of priority of the zones of two `Map`s combined by `Map::merge`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Priority {
+    /// The zones of the existing `Map` are played wherever they overlap the merged zones.
+    Existing,
+    /// The merged zones are played wherever they overlap the zones of the existing `Map`.
+    Merged,
+    /// The zones of both maps are ordered by range as though each merged zone were inserted via
+    /// `Map::insert_zone`.
+    Range,
+}
+
+/// The event upon which the samples of a zone are played.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Trigger {
+    /// The zone is played by `note_on`.
+    Attack,
+    /// The zone is played by `note_off` using the velocity of the released note, i.e. the sound of
+    /// a piano damper or of a finger leaving a guitar string.
+    Release,
+}
+
+/// A distance into a `Sample`'s audio.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StartOffset {
+    /// A number of frames.
+    Frames(usize),
+    /// A duration, converted to frames at the playback sample rate.
+    Ms(time::Ms),
+}
+
+/// The shape of the gain curve used to blend zones across the region in which they overlap.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Crossfade {
+    /// The gains of both zones sum to `1.0` at every point within the overlap.
+    Linear,
+    /// The powers of both zones sum to `1.0` at every point within the overlap, keeping the
+    /// perceived loudness constant when blending uncorrelated recordings.
+    EqualPower,
+}
+
+/// The method by which a zone chooses between its `sample` and `alternates` on each `note_on`.
+///
+/// Each variant carries the state necessary for choosing the next sample in the group.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Alternation {
+    /// Cycle through the group in order, sharing a single counter across the whole zone.
+    RoundRobin {
+        /// The index of the next sample within the group.
+        position: usize,
+    },
+    /// Cycle through the group in order, keeping a separate counter for each `Step`.
+    RoundRobinPerStep {
+        /// The index of the next sample within the group for each step from `MIN_STEP`.
+        positions: Vec<usize>,
+    },
+    /// Pick a random sample from the group, never picking the same sample twice in a row.
+    Random {
+        /// The index of the most recently picked sample within the group.
+        last: Option<usize>,
+    },
+}
+
+/// A region of a `Sample`'s audio that repeats while the note is held.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Loop {
+    /// The index of the first frame of the loop.
+    pub start: usize,
+    /// The index of the frame following the last frame of the loop.
+    pub end: usize,
+    /// The direction in which the loop is played.
+    pub mode: LoopMode,
+    /// What happens to the loop once the note is released.
+    pub release: LoopRelease,
+}
+
+/// The direction in which a `Loop` is played.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LoopMode {
+    /// Jump from the end of the loop back to its start.
+    Forward,
+    /// Upon first reaching the end of the loop, play the loop in reverse, jumping from its start
+    /// back to its end.
+    Backward,
+    /// Alternate between playing the loop forwards and in reverse.
+    PingPong,
+}
+
+/// What happens to a `Loop` once its note is released.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LoopRelease {
+    /// Continue looping throughout the release of the note.
+    Continue,
+    /// Leave the loop, playing forwards through to the end of the audio.
+    PlayToEnd,
+}
+
+/// Where `Map::from_pitched_samples` places the boundary between the step ranges of two samples
+/// with neighbouring roots.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Split {
+    /// Halfway between the roots so that each sample is transposed as little as possible.
+    Midpoint,
+    /// Directly beneath the upper root so that samples are only ever transposed upwards.
+    Upward,
+    /// Directly above the lower root so that samples are only ever transposed downwards.
+    Downward,
+}
+
+/// A problem within a `Map` found by `Map::validate`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Diagnostic {
+    /// The step range of the zone at the given index has a `min` greater than its `max`.
+    InvertedStepRange { zone: usize },
+    /// The velocity range of the zone at the given index has a `min` greater than its `max`.
+    InvertedVelRange { zone: usize },
+    /// The step range of the zone at the given index extends beyond `MIN_STEP` or `MAX_STEP`.
+    StepOutOfRange { zone: usize },
+    /// The zone at the given index can never be played as earlier zones cover all of its range.
+    Hidden { zone: usize },
+    /// No zone covers the given range of steps at any velocity.
+    ///
+    /// A step is also reported if only some of the fractional steps rounding to it are uncovered,
+    /// i.e. between the boundaries of microtonal zones.
+    StepGap { steps: Range<Step> },
+    /// No zone covers the velocities between (but excluding) the given `vel` bounds for the
+    /// given range of steps, or for some of the fractional steps rounding to them.
+    VelGap { steps: Range<Step>, vel: Range<Velocity> },
+}
+
+/// A precomputed 2-dimensional index over step and velocity used to find the first zone within
+/// which a step and velocity exist without scanning every zone.
+///
+/// Only the zones of a single articulation are indexed. Zones with `controllers` are indexed
+/// regardless of the current controller values, which are instead checked upon lookup, so that
+/// setting a controller requires no re-indexing.
+#[derive(Clone, PartialEq)]
+struct Index {
+    /// The velocity segments for each step from `MIN_STEP` to `MAX_STEP`.
+    steps: Vec<VelIndex>,
+}
+
+/// Divides the velocity range of a single step into segments over which the zones within which
+/// the step and velocity exist remain the same.
+///
+/// The candidates of each segment are the indices of the zones over the segment in order of
+/// priority, up to and including the first zone without `controllers`. The first zone played is
+/// the first candidate whose `controllers` ranges contain the current controller values.
+#[derive(Clone, Debug, PartialEq)]
+struct VelIndex {
+    /// The sorted, de-duplicated `min` and `max` velocities of each zone over the step.
+    bounds: Vec<Velocity>,
+    /// The candidates over each of the `bounds`.
+    at: Vec<Vec<usize>>,
+    /// The candidates over the span between each consecutive pair of `bounds`.
+    between: Vec<Vec<usize>>,
 }
 
 /// A continuous range of `T` from the `min` to the `max`.
@@ -53,6 +377,401 @@ impl Range<Velocity> {
     }
 }
 
+impl StepVelRange {
+    /// Whether or not any step and velocity lie within both ranges.
+    pub fn overlaps(&self, other: &StepVelRange) -> bool {
+        self.step.min <= other.step.max && self.step.max >= other.step.min
+            && self.vel.min <= other.vel.max && self.vel.max >= other.vel.min
+    }
+}
+
+impl Alternation {
+
+    /// A round-robin that shares a single counter across the whole zone.
+    pub fn round_robin() -> Self {
+        Alternation::RoundRobin { position: 0 }
+    }
+
+    /// A round-robin that keeps a separate counter for each `Step` within the zone.
+    pub fn round_robin_per_step() -> Self {
+        let n_steps = (MAX_STEP - MIN_STEP + 1) as usize;
+        Alternation::RoundRobinPerStep { positions: vec![0; n_steps] }
+    }
+
+    /// Picks randomly from the group while avoiding an immediate repeat of the last pick.
+    pub fn random() -> Self {
+        Alternation::Random { last: None }
+    }
+
+    /// Returns the index of the sample within a group of `len` samples that should be played for
+    /// the given `step` and advances the state ready for the next `note_on`.
+    ///
+    /// The given `rng` is only used by the `Random` alternation.
+    pub fn next<R>(&mut self, step: Step, len: usize, rng: &mut R) -> usize
+        where R: rand::Rng,
+    {
+        if len == 0 {
+            return 0;
+        }
+        let position = match *self {
+            Alternation::RoundRobin { ref mut position } => position,
+            Alternation::RoundRobinPerStep { ref mut positions } => {
+                match positions.get_mut((step - MIN_STEP) as usize) {
+                    Some(position) => position,
+                    None => return 0,
+                }
+            },
+            Alternation::Random { ref mut last } => {
+                // Pick from all samples but the last, skipping over the last pick's index.
+                let idx = match *last {
+                    Some(last) if last < len && len > 1 => {
+                        let idx = rng.gen_range(0, len - 1);
+                        if idx >= last { idx + 1 } else { idx }
+                    },
+                    _ => rng.gen_range(0, len),
+                };
+                *last = Some(idx);
+                return idx;
+            },
+        };
+        let idx = *position % len;
+        *position = (idx + 1) % len;
+        idx
+    }
+
+    /// Resets the state so that the next `note_on` plays the first sample in the group.
+    pub fn reset(&mut self) {
+        match *self {
+            Alternation::RoundRobin { ref mut position } => *position = 0,
+            Alternation::RoundRobinPerStep { ref mut positions } => {
+                for position in positions.iter_mut() {
+                    *position = 0;
+                }
+            },
+            Alternation::Random { ref mut last } => *last = None,
+        }
+    }
+
+}
+
+impl Loop {
+
+    /// A `Forward` loop over the given range of frames that continues throughout the release.
+    pub fn forward(start: usize, end: usize) -> Self {
+        Loop {
+            start: start,
+            end: end,
+            mode: LoopMode::Forward,
+            release: LoopRelease::Continue,
+        }
+    }
+
+    /// Whether or not the loop lies within audio of the given length and contains at least one
+    /// frame.
+    pub fn is_valid(&self, audio_len: usize) -> bool {
+        self.start < self.end && self.end <= audio_len
+    }
+
+}
+
+impl StartOffset {
+
+    /// The offset scaled by the given amount, i.e. `0.5` for half of the offset.
+    pub fn scale(&self, amount: f32) -> Self {
+        match *self {
+            StartOffset::Frames(frames) => StartOffset::Frames((frames as f32 * amount).round() as usize),
+            StartOffset::Ms(ms) => StartOffset::Ms(time::Ms(ms.ms() * amount as f64)),
+        }
+    }
+
+    /// The number of frames within the offset at the given sample rate.
+    pub fn frames(&self, sample_hz: f64) -> usize {
+        match *self {
+            StartOffset::Frames(frames) => frames,
+            StartOffset::Ms(ms) => std::cmp::max(ms.samples(sample_hz), 0) as usize,
+        }
+    }
+
+}
+
+impl Split {
+
+    /// The highest step that should be played by the sample with the `lower` root, given the
+    /// `upper` root of its neighbour.
+    pub fn boundary(&self, lower: Step, upper: Step) -> Step {
+        match *self {
+            Split::Midpoint => lower + (upper - lower) / 2,
+            Split::Upward => upper - 1,
+            Split::Downward => lower,
+        }
+    }
+
+}
+
+impl Crossfade {
+
+    /// The gain of a zone that is fading in, where `t` is the position through the overlap from
+    /// `0.0` to `1.0`.
+    pub fn fade_in(&self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match *self {
+            Crossfade::Linear => t,
+            Crossfade::EqualPower => (t * std::f32::consts::FRAC_PI_2).sin(),
+        }
+    }
+
+    /// The gain of a zone that is fading out, where `t` is the position through the overlap from
+    /// `0.0` to `1.0`.
+    pub fn fade_out(&self, t: f32) -> f32 {
+        self.fade_in(1.0 - t)
+    }
+
+    /// The gain at `x` of a zone spanning `min` to `max` that overlaps with each of the given
+    /// `neighbours`.
+    ///
+    /// The zone fades in across its overlap with the neighbours that begin beneath it and fades
+    /// out across its overlap with the neighbours that end above it. Neighbours that entirely
+    /// contain (or are contained by) the zone do not affect its gain.
+    pub fn gain<I>(&self, min: f32, max: f32, neighbours: I, x: f32) -> f32
+        where I: IntoIterator<Item=(f32, f32)>,
+    {
+        let (mut fade_in_end, mut fade_out_start) = (min, max);
+        for (n_min, n_max) in neighbours {
+            if n_min < min && n_max >= min && n_max < max {
+                fade_in_end = fade_in_end.max(n_max);
+            } else if n_max > max && n_min <= max && n_min > min {
+                fade_out_start = fade_out_start.min(n_min);
+            }
+        }
+        let mut gain = 1.0;
+        if fade_in_end > min && x < fade_in_end {
+            gain *= self.fade_in((x - min) / (fade_in_end - min));
+        }
+        if fade_out_start < max && x > fade_out_start {
+            gain *= self.fade_out((x - fade_out_start) / (max - fade_out_start));
+        }
+        gain
+    }
+
+}
+
+impl<A> SampleOverRange<A> {
+
+    /// Construct a zone that plays the given `sample` on every `note_on`.
+    pub fn new(range: StepVelRange, sample: Sample<A>) -> Self {
+        SampleOverRange {
+            range: range,
+            sample: sample,
+            alternates: vec![],
+            alternation: Alternation::round_robin(),
+            vel_crossfade: None,
+            step_crossfade: None,
+            fractional_step: None,
+            trigger: Trigger::Attack,
+            release_half_life: None,
+            articulation: None,
+            controllers: vec![],
+            choke_group: None,
+            start_offset: None,
+            id: None,
+        }
+    }
+
+    /// Construct a zone that plays the given `sample` on every `note_off`.
+    pub fn release(range: StepVelRange, sample: Sample<A>, half_life: Option<time::Ms>) -> Self {
+        let mut zone = SampleOverRange::new(range, sample);
+        zone.trigger = Trigger::Release;
+        zone.release_half_life = half_life;
+        zone
+    }
+
+    /// Construct a zone that covers the given range of fractional steps.
+    ///
+    /// The zone's `range.step` is set to the range of `Step`s to which the fractional steps round.
+    pub fn fractional(step: Range<f32>, vel: Range<Velocity>, sample: Sample<A>) -> Self {
+        let range = StepVelRange {
+            step: Range { min: step.min.round() as Step, max: step.max.round() as Step },
+            vel: vel,
+        };
+        let mut zone = SampleOverRange::new(range, sample);
+        zone.fractional_step = Some(step);
+        zone
+    }
+
+    /// Construct a zone that covers the given range of frequencies.
+    pub fn hz(hz: Range<pitch::Hz>, vel: Range<Velocity>, sample: Sample<A>) -> Self {
+        let step = Range { min: hz.min.step(), max: hz.max.step() };
+        SampleOverRange::fractional(step, vel, sample)
+    }
+
+    /// Is the given fractional step and velocity within the zone.
+    ///
+    /// The step is rounded to the nearest `Step` unless the zone has a `fractional_step` range.
+    pub fn is_over(&self, step: f32, vel: Velocity) -> bool {
+        self.is_over_step(step) && self.range.vel.is_over(vel)
+    }
+
+    /// Is the given fractional step within the zone, regardless of velocity.
+    fn is_over_step(&self, step: f32) -> bool {
+        self.range.step.is_over(step.round() as Step) && match self.fractional_step {
+            Some(ref fractional_step) => fractional_step.is_over(step),
+            None => true,
+        }
+    }
+
+    /// Whether the zone is played upon the given `trigger` under the given `selection`.
+    fn is_playable(&self, trigger: Trigger, selection: &Selection) -> bool {
+        self.is_in_articulation(trigger, selection.articulation) && self.is_selected(selection)
+    }
+
+    /// Whether the zone is played upon the given `trigger` within the given `articulation`,
+    /// regardless of the values of its `controllers`.
+    fn is_in_articulation(&self, trigger: Trigger, articulation: usize) -> bool {
+        self.trigger == trigger && self.articulation.map_or(true, |a| a == articulation)
+    }
+
+    /// Whether the value of each of the zone's `controllers` under the given `selection` lies
+    /// within its paired range.
+    fn is_selected(&self, selection: &Selection) -> bool {
+        self.controllers.iter().all(|&(controller, ref range)| {
+            range.is_over(selection.controller(controller))
+        })
+    }
+
+    /// The lowest and highest fractional steps covered by the zone.
+    pub fn step_bounds(&self) -> (f32, f32) {
+        match self.fractional_step {
+            Some(ref step) => (step.min, step.max),
+            None => (self.range.step.min as f32, self.range.step.max as f32),
+        }
+    }
+
+    /// The gain of a `Release` zone for a note that was held for the given duration.
+    pub fn release_gain(&self, held: time::Ms) -> f32 {
+        match self.release_half_life {
+            Some(half_life) if half_life.ms() > 0.0 => 0.5f32.powf((held.ms() / half_life.ms()) as f32),
+            _ => 1.0,
+        }
+    }
+
+    /// The offset from which a note played at the given velocity begins, if the zone has a
+    /// `start_offset`.
+    pub fn start_offset_at(&self, vel: Velocity) -> Option<StartOffset> {
+        let vel = vel.max(0.0).min(1.0);
+        self.start_offset.map(|offset| offset.scale(1.0 - vel))
+    }
+
+    /// Maps the `SampleOverRange` with some `Audio` type `A` to a `SampleOverRange` with some
+    /// `Audio` type `B`, applying the given function to the audio of every sample within the group.
+    pub fn map_audio<F, B>(self, mut map: F) -> SampleOverRange<B>
+        where F: FnMut(A) -> B,
+    {
+        let SampleOverRange {
+            range, sample, alternates, alternation, vel_crossfade, step_crossfade, fractional_step,
+            trigger, release_half_life, articulation, controllers, choke_group, start_offset, id,
+        } = self;
+        SampleOverRange {
+            range: range,
+            sample: sample.map_audio(&mut map),
+            alternates: alternates.into_iter().map(|sample| sample.map_audio(&mut map)).collect(),
+            alternation: alternation,
+            vel_crossfade: vel_crossfade,
+            step_crossfade: step_crossfade,
+            fractional_step: fractional_step,
+            trigger: trigger,
+            release_half_life: release_half_life,
+            articulation: articulation,
+            controllers: controllers,
+            choke_group: choke_group,
+            start_offset: start_offset,
+            id: id,
+        }
+    }
+
+    /// Whether or not the zone is blended with the zones that it overlaps in either dimension.
+    pub fn is_crossfading(&self) -> bool {
+        self.vel_crossfade.is_some() || self.step_crossfade.is_some()
+    }
+
+    /// The number of samples within the zone's group, including `sample`.
+    pub fn group_len(&self) -> usize {
+        1 + self.alternates.len()
+    }
+
+    /// Returns the sample within the group that should be played for the given `step` and
+    /// advances the `alternation` ready for the next `note_on`.
+    pub fn next_sample<R>(&mut self, step: Step, rng: &mut R) -> &Sample<A>
+        where R: rand::Rng,
+    {
+        let idx = self.next_sample_idx(step, rng);
+        self.group_sample(idx).unwrap_or(&self.sample)
+    }
+
+    /// Returns the index within the group of the sample returned by `next_sample`.
+    pub fn next_sample_idx<R>(&mut self, step: Step, rng: &mut R) -> usize
+        where R: rand::Rng,
+    {
+        let len = self.group_len();
+        self.alternation.next(step, len, rng)
+    }
+
+    /// The sample at the given index within the group, where `0` is the zone's `sample` and all
+    /// following indices refer to its `alternates`.
+    pub fn group_sample(&self, idx: usize) -> Option<&Sample<A>> {
+        match idx {
+            0 => Some(&self.sample),
+            n => self.alternates.get(n - 1),
+        }
+    }
+
+}
+
+impl Layers {
+
+    /// An empty set of layers.
+    pub fn new() -> Self {
+        Layers {
+            len: 0,
+            layers: [(SampleIdx { zone: 0, id: None, sample: 0 }, 0.0); MAX_LAYERS],
+        }
+    }
+
+    /// Adds the given sample at the given gain, returning `false` if there are already
+    /// `MAX_LAYERS` layers.
+    pub fn push(&mut self, idx: SampleIdx, gain: f32) -> bool {
+        if self.len == MAX_LAYERS {
+            return false;
+        }
+        self.layers[self.len] = (idx, gain);
+        self.len += 1;
+        true
+    }
+
+    /// The number of layers.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether or not there are no layers, i.e. no zone was found for the note.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Each sample paired with its gain.
+    pub fn iter<'a>(&'a self) -> std::slice::Iter<'a, (SampleIdx, f32)> {
+        self.layers[..self.len].iter()
+    }
+
+}
+
+impl<'a, A> Iterator for Iter<'a, A> {
+    type Item = (ZoneId, &'a SampleOverRange<A>);
+    fn next(&mut self) -> Option<Self::Item> {
+        // Zones added to the `pairs` directly have no id until the map is re-indexed.
+        self.pairs.by_ref().filter_map(|zone| zone.id.map(|id| (id, zone))).next()
+    }
+}
+
 impl<A> Sample<A> {
 
     /// Constructor for a new `Sample` with the given base Hz and Velocity.
@@ -61,6 +780,12 @@ impl<A> Sample<A> {
             base_hz: base_hz,
             base_vel: base_vel,
             audio: audio,
+            key_tracking: 1.0,
+            one_shot: false,
+            tune_cents: 0.0,
+            gain_db: 0.0,
+            pan: 0.0,
+            sustain_loop: None,
         }
     }
 
@@ -68,103 +793,1167 @@ impl<A> Sample<A> {
     pub fn map_audio<F, B>(self, map: F) -> Sample<B>
         where F: FnOnce(A) -> B,
     {
-        let Sample { base_hz, base_vel, audio } = self;
+        let Sample {
+            base_hz, base_vel, audio, key_tracking, one_shot, tune_cents, gain_db, pan, sustain_loop,
+        } = self;
         Sample {
             base_hz: base_hz,
             base_vel: base_vel,
             audio: map(audio),
+            key_tracking: key_tracking,
+            one_shot: one_shot,
+            tune_cents: tune_cents,
+            gain_db: gain_db,
+            pan: pan,
+            sustain_loop: sustain_loop,
         }
     }
 
 }
 
-impl<A> Map<A>
-    where A: Audio,
+impl<A> PartialEq for Map<A>
+    where A: PartialEq,
 {
-
-    /// Construct an empty `Map`.
-    pub fn empty() -> Self {
-        Map { pairs: vec![] }
+    fn eq(&self, other: &Self) -> bool {
+        // The `next_id` and `indices` are bookkeeping and are not compared.
+        self.pairs == other.pairs
+            && self.key_switches == other.key_switches
+            && self.choke_fade == other.choke_fade
+            && self.selection == other.selection
     }
+}
 
-    /// Construct a `Map` from a series of mappings, starting from (-C2, 1.0).
-    pub fn from_sequential_steps<I>(mappings: I) -> Self
-        where I: IntoIterator<Item=(Step, Velocity, Sample<A>)>,
-    {
-        let (mut last_step, mut last_vel) = (0, 1.0);
-        let pairs = mappings.into_iter().map(|(step, vel, sample)| {
-            let range = StepVelRange {
-                step: Range { min: last_step, max: step },
-                vel: Range { min: last_vel, max: vel },
-            };
-            last_step = step;
-            last_vel = vel;
-            SampleOverRange { range: range, sample: sample }
-        }).collect();
-        Map { pairs: pairs }
+impl std::fmt::Debug for Index {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "Index {{ steps: {:?} }}", self.steps.len())
     }
+}
 
-    /// Creates a `Map` with a single sample mapped to the entire Step and Velocity range.
-    pub fn from_single_sample(sample: Sample<A>) -> Self {
-        let range = StepVelRange {
-            step: Range { min: MIN_STEP, max: MAX_STEP },
-            vel: Range { min: 0.0, max: 1.0 },
-        };
-        let pairs = vec![SampleOverRange { range: range, sample: sample }];
-        Map { pairs: pairs }
+impl Selection {
+
+    /// The current value of the given controller, which is `0.0` until it is first set.
+    fn controller(&self, controller: Controller) -> f32 {
+        self.controllers.iter()
+            .find(|&&(c, _)| c == controller)
+            .map_or(0.0, |&(_, value)| value)
     }
 
-    /// Inserts a range -> audio mapping into the Map.
-    pub fn insert(&mut self, range: StepVelRange, sample: Sample<A>) {
-        for i in 0..self.pairs.len() {
-            if self.pairs[i].range > range {
-                self.pairs.insert(i, SampleOverRange { range: range, sample: sample });
-                return;
-            }
+    /// Sets the current value of the given controller.
+    fn set_controller(&mut self, controller: Controller, value: f32) {
+        match self.controllers.iter_mut().find(|&&mut (c, _)| c == controller) {
+            Some(&mut (_, ref mut current)) => *current = value,
+            None => self.controllers.push((controller, value)),
         }
-        self.pairs.push(SampleOverRange { range: range, sample: sample });
     }
 
-    /// Returns the `Audio` associated with the range within which the given hz and velocity exist.
+}
+
+impl Index {
+
+    /// Builds an index for each articulation referred to by the given zones, followed by an index
+    /// for all other articulations.
+    fn per_articulation<A>(pairs: &[SampleOverRange<A>]) -> Vec<Self> {
+        (0..num_articulations(pairs) + 1)
+            .map(|articulation| Index::new(pairs, articulation))
+            .collect()
+    }
+
+    /// Builds the index of the `Attack` zones within the given `articulation` for every step from
+    /// the given zones.
+    fn new<A>(pairs: &[SampleOverRange<A>], articulation: usize) -> Self {
+        let steps = (MIN_STEP..MAX_STEP + 1)
+            .map(|step| VelIndex::new(step, Trigger::Attack, articulation, pairs))
+            .collect();
+        Index { steps: steps }
+    }
+
+    /// The candidates for the first `Attack` zone within which the given step and velocity exist.
     ///
-    /// TODO: This would probably be quicker with some sort of specialised RangeMap.
-    pub fn sample(&self, hz: pitch::Hz, vel: Velocity) -> Option<Sample<A>> {
-        let step = hz.step().round() as Step;
-        for &SampleOverRange { ref range, ref sample } in &self.pairs {
-            if range.step.is_over(step) && range.vel.is_over(vel) {
-                return Some(sample.clone());
+    /// Returns `None` if the step lies outside of the indexed range.
+    fn get(&self, step: Step, vel: Velocity) -> Option<&[usize]> {
+        if step < MIN_STEP || step > MAX_STEP {
+            return None;
+        }
+        self.steps.get((step - MIN_STEP) as usize).map(|vel_index| vel_index.get(vel))
+    }
+
+    /// Updates the index after the given `zone` was inserted into the `pairs` at `idx`.
+    fn insert<A>(&mut self, idx: usize, pairs: &[SampleOverRange<A>], articulation: usize) {
+        // All zones following the inserted zone have been shifted along by one.
+        for vel_index in &mut self.steps {
+            for candidates in vel_index.at.iter_mut().chain(vel_index.between.iter_mut()) {
+                for zone_idx in candidates.iter_mut() {
+                    if *zone_idx >= idx {
+                        *zone_idx += 1;
+                    }
+                }
             }
         }
-        None
+
+        // Only the steps covered by the inserted zone need rebuilding.
+        let range = &pairs[idx].range.step;
+        let (min, max) = (std::cmp::max(range.min, MIN_STEP), std::cmp::min(range.max, MAX_STEP));
+        for step in min..max + 1 {
+            self.steps[(step - MIN_STEP) as usize] = VelIndex::new(step, Trigger::Attack, articulation, pairs);
+        }
     }
 
 }
 
+impl VelIndex {
 
-#[cfg(feature="wav")]
-pub mod wav {
-    use audio;
-    use map;
-    use pitch;
-    use sample;
-    use std;
+    /// Builds the velocity segments of the zones played upon the given `trigger` within the given
+    /// `articulation` for the given step.
+    fn new<A>(step: Step, trigger: Trigger, articulation: usize, pairs: &[SampleOverRange<A>]) -> Self {
+        let over: Vec<usize> = (0..pairs.len())
+            .filter(|&i| pairs[i].is_in_articulation(trigger, articulation) && pairs[i].range.step.is_over(step))
+            .collect();
 
+        let mut bounds: Vec<Velocity> = over.iter()
+            .flat_map(|&i| Some(pairs[i].range.vel.min).into_iter().chain(Some(pairs[i].range.vel.max)))
+            .filter(|vel| !vel.is_nan())
+            .collect();
+        bounds.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        bounds.dedup();
 
-    /// An alias for the `wav` `Sample` type.
-    pub type Sample<F> = super::Sample<std::sync::Arc<audio::wav::Audio<F>>>;
+        let candidates = |vel: Velocity| {
+            let mut candidates = vec![];
+            for &i in over.iter().filter(|&&i| pairs[i].range.vel.is_over(vel)) {
+                candidates.push(i);
+                if pairs[i].controllers.is_empty() {
+                    break;
+                }
+            }
+            candidates
+        };
+        let at = bounds.iter().map(|&vel| candidates(vel)).collect();
+        let between = bounds.windows(2).map(|w| candidates(w[0] + (w[1] - w[0]) / 2.0)).collect();
 
+        VelIndex {
+            bounds: bounds,
+            at: at,
+            between: between,
+        }
+    }
 
-    impl<F> Sample<F>
-        where F: sample::Frame,
-              F::Sample: sample::Duplex<f64> + sample::Duplex<i32>,
-              Box<[F::Sample]>: sample::ToBoxedFrameSlice<F>,
-    {
+    /// A velocity from each segment paired with the candidates over the segment.
+    fn segments<'a>(&'a self) -> Box<Iterator<Item=(Velocity, &'a [usize])> + 'a> {
+        let at = self.bounds.iter().cloned().zip(self.at.iter().map(|c| &c[..]));
+        let between = self.bounds.windows(2)
+            .map(|w| w[0] + (w[1] - w[0]) / 2.0)
+            .zip(self.between.iter().map(|c| &c[..]));
+        Box::new(at.chain(between))
+    }
 
-        /// Loads a `Sample` from the `.wav` file at the given `path`.
-        ///
-        /// If the `.wav` file has a musical note in the file name, that note's playback frequency in
-        /// `hz` will be used as the `base_hz`.
-        ///
+    /// The candidates for the first zone within which the given velocity exists.
+    fn get(&self, vel: Velocity) -> &[usize] {
+        let search = self.bounds.binary_search_by(|bound| {
+            bound.partial_cmp(&vel).unwrap_or(std::cmp::Ordering::Less)
+        });
+        match search {
+            Ok(i) => &self.at[i],
+            Err(i) if i > 0 && i < self.bounds.len() => &self.between[i - 1],
+            Err(_) => &[],
+        }
+    }
+
+}
+
+impl<A> Map<A> {
+
+    /// Construct a `Map` from the given zones in order of priority.
+    pub fn from_pairs(pairs: Vec<SampleOverRange<A>>) -> Self {
+        let indices = Index::per_articulation(&pairs);
+        let mut map = Map {
+            pairs: pairs,
+            next_id: 0,
+            key_switches: None,
+            choke_fade: time::Ms(DEFAULT_CHOKE_FADE_MS),
+            selection: Selection { articulation: 0, controllers: vec![] },
+            indices: indices,
+        };
+        map.assign_ids();
+        map.reserve_controllers();
+        map
+    }
+
+    /// Rebuilds the index used to look up zones.
+    ///
+    /// This must be called after modifying the `pairs` directly. Zones without an `id`, or whose
+    /// `id` is shared with an earlier zone (i.e. a cloned zone), are given new ids.
+    pub fn reindex(&mut self) {
+        self.assign_ids();
+        self.indices = Index::per_articulation(&self.pairs);
+        self.reserve_controllers();
+    }
+
+    /// Reserves room for the value of every controller referred to by the zones, so that setting
+    /// their values never allocates.
+    fn reserve_controllers(&mut self) {
+        let n = self.pairs.iter().map(|zone| zone.controllers.len()).sum();
+        self.selection.controllers.reserve(n);
+    }
+
+    /// A `ZoneId` that has not yet been given to any zone.
+    fn new_id(&mut self) -> ZoneId {
+        let id = ZoneId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// Gives a new id to each zone without an `id` and to each zone whose `id` is shared with an
+    /// earlier zone.
+    fn assign_ids(&mut self) {
+        // Ids given to zones elsewhere, i.e. by another map, must never be given out again.
+        if let Some(ZoneId(max)) = self.pairs.iter().filter_map(|zone| zone.id).max() {
+            self.next_id = std::cmp::max(self.next_id, max + 1);
+        }
+        let mut taken = std::collections::HashSet::new();
+        for i in 0..self.pairs.len() {
+            let is_taken = self.pairs[i].id.map_or(true, |id| !taken.insert(id));
+            if is_taken {
+                let id = self.new_id();
+                self.pairs[i].id = Some(id);
+                taken.insert(id);
+            }
+        }
+    }
+
+    /// Each zone in order of priority, along with its `ZoneId`.
+    pub fn iter<'a>(&'a self) -> Iter<'a, A> {
+        Iter { pairs: self.pairs.iter() }
+    }
+
+    /// The `ZoneId` of the zone at the given index within the `pairs`.
+    pub fn zone_id(&self, idx: usize) -> Option<ZoneId> {
+        self.pairs.get(idx).and_then(|zone| zone.id)
+    }
+
+    /// The index within the `pairs` of the zone with the given id.
+    pub fn position(&self, id: ZoneId) -> Option<usize> {
+        self.pairs.iter().position(|zone| zone.id == Some(id))
+    }
+
+    /// The zone with the given id.
+    pub fn zone(&self, id: ZoneId) -> Option<&SampleOverRange<A>> {
+        self.position(id).map(|idx| &self.pairs[idx])
+    }
+
+    /// The zone with the given id.
+    ///
+    /// If the zone's range, trigger, articulation or controllers are changed, `Map::reindex` must
+    /// be called afterwards. Until then, lookups such as `Map::zone_idx` and `Map::sample` still
+    /// use the zone's previous range and may find the wrong zone or none at all.
+    pub fn zone_mut(&mut self, id: ZoneId) -> Option<&mut SampleOverRange<A>> {
+        match self.position(id) {
+            Some(idx) => Some(&mut self.pairs[idx]),
+            None => None,
+        }
+    }
+
+    /// The ids of every zone within which the given step and velocity exist, in order of priority.
+    ///
+    /// Unlike `Map::zone_idx`, this includes zones that are hidden by higher priority zones and
+    /// zones that are not playable under the current articulation and controller values.
+    pub fn zones_at(&self, step: Step, vel: Velocity) -> Vec<ZoneId> {
+        self.iter()
+            .filter(|&(_, zone)| zone.is_over(step as f32, vel))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// The ids of every zone whose range overlaps the given range, in order of priority.
+    pub fn zones_in(&self, range: StepVelRange) -> Vec<ZoneId> {
+        self.iter()
+            .filter(|&(_, zone)| zone.range.overlaps(&range))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Replaces the `sample` of the zone with the given id, returning the previous `sample`.
+    ///
+    /// Returns `None`, leaving the map unchanged, if there is no zone with the given id. Notes
+    /// already playing the zone's `sample` continue from the same position within the new one.
+    pub fn replace(&mut self, id: ZoneId, sample: Sample<A>) -> Option<Sample<A>> {
+        self.zone_mut(id).map(|zone| std::mem::replace(&mut zone.sample, sample))
+    }
+
+    /// Removes the zone with the given id, returning it.
+    ///
+    /// Notes still playing the removed zone fall silent, while notes playing every other zone
+    /// continue uninterrupted.
+    pub fn remove(&mut self, id: ZoneId) -> Option<SampleOverRange<A>> {
+        self.position(id).map(|idx| {
+            let zone = self.pairs.remove(idx);
+            self.reindex();
+            zone
+        })
+    }
+
+    /// The index of the active articulation.
+    fn index(&self) -> &Index {
+        let last = self.indices.len() - 1;
+        &self.indices[std::cmp::min(self.selection.articulation, last)]
+    }
+
+    /// The articulation whose zones are currently played.
+    pub fn articulation(&self) -> usize {
+        self.selection.articulation
+    }
+
+    /// Switch to playing the zones of the given articulation.
+    ///
+    /// Zones without an `articulation` are played regardless. Each articulation is indexed ahead
+    /// of time, so switching is cheap enough to do within a `note_on`.
+    pub fn set_articulation(&mut self, articulation: usize) {
+        self.selection.articulation = articulation;
+    }
+
+    /// The current value of the given controller, which is `0.0` until it is first set.
+    pub fn controller(&self, controller: Controller) -> f32 {
+        self.selection.controller(controller)
+    }
+
+    /// The controllers that have been set along with their current values.
+    pub fn controllers(&self) -> &[(Controller, f32)] {
+        &self.selection.controllers
+    }
+
+    /// Sets the value of the given controller, switching to the zones whose `controllers` ranges
+    /// contain the new value.
+    ///
+    /// Controller values are checked upon lookup rather than indexed, so this never re-indexes the
+    /// zones. Nor does it allocate for the controllers referred to by the zones, making it cheap
+    /// enough to call for every value of a continuous stream on the audio thread.
+    pub fn set_controller(&mut self, controller: Controller, value: f32) {
+        self.selection.set_controller(controller, value);
+    }
+
+    /// Whether or not the given hz lies within the `key_switches`.
+    pub fn is_key_switch(&self, hz: pitch::Hz) -> bool {
+        self.key_switch_articulation(hz).is_some()
+    }
+
+    /// If the given hz lies within the `key_switches`, switches to its articulation and returns
+    /// `true`. Otherwise, returns `false`.
+    pub fn key_switch(&mut self, hz: pitch::Hz) -> bool {
+        match self.key_switch_articulation(hz) {
+            Some(articulation) => {
+                self.set_articulation(articulation);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// The articulation selected by the key switch at the given hz, if there is one.
+    fn key_switch_articulation(&self, hz: pitch::Hz) -> Option<usize> {
+        let step = hz.step().round() as Step;
+        match self.key_switches {
+            Some(ref range) if range.is_over(step) => Some((step - range.min) as usize),
+            _ => None,
+        }
+    }
+
+    /// The number of articulations referred to by the zones, which is always at least `1`.
+    fn num_articulations(&self) -> usize {
+        num_articulations(&self.pairs)
+    }
+
+    /// The `Sample` referred to by the given index, as returned by `Map::sample_idx` or within the
+    /// `Layers` returned by `Map::layer_indices`.
+    ///
+    /// Returns `None` if the zone is no longer found at the index, i.e. if the `pairs` have been
+    /// modified since, in which case the index may be brought up to date with `Map::locate`.
+    pub fn get_sample(&self, idx: SampleIdx) -> Option<&Sample<A>> {
+        self.pairs.get(idx.zone)
+            .and_then(|zone| if zone.id == idx.id { zone.group_sample(idx.sample) } else { None })
+    }
+
+    /// Brings the given index up to date with the current position of its zone within the
+    /// `pairs`, which may have moved since the index was produced.
+    ///
+    /// Returns `None` if the zone or its sample has since been removed.
+    ///
+    /// This only searches the `pairs` if the zone has moved.
+    pub fn locate(&self, idx: SampleIdx) -> Option<SampleIdx> {
+        let zone = match self.pairs.get(idx.zone) {
+            Some(zone) if zone.id == idx.id => idx.zone,
+            _ => match idx.id.and_then(|id| self.position(id)) {
+                Some(zone) => zone,
+                None => return None,
+            },
+        };
+        self.pairs[zone].group_sample(idx.sample).map(|_| SampleIdx { zone: zone, ..idx })
+    }
+
+    /// The index of the first `Attack` zone within which the given step and velocity exist.
+    ///
+    /// This is `O(log n)` for steps within `MIN_STEP` and `MAX_STEP`, falling back to a linear
+    /// scan for steps beyond them.
+    pub fn zone_idx(&self, step: Step, vel: Velocity) -> Option<usize> {
+        self.fractional_zone_idx(step as f32, vel)
+    }
+
+    /// The index of the first `Attack` zone within which the given fractional step and velocity
+    /// exist.
+    ///
+    /// Unlike `zone_idx`, this respects the `fractional_step` range of microtonal zones.
+    pub fn fractional_zone_idx(&self, step: f32, vel: Velocity) -> Option<usize> {
+        let selection = &self.selection;
+        let is_over = |zone: &SampleOverRange<A>| {
+            zone.is_playable(Trigger::Attack, selection) && zone.is_over(step, vel)
+        };
+        let first = self.index().get(step.round() as Step, vel).map(|candidates| {
+            candidates.iter().cloned().find(|&i| {
+                self.pairs.get(i).map_or(true, |zone| zone.is_selected(selection))
+            })
+        });
+        match first {
+            Some(Some(idx)) => match self.pairs.get(idx) {
+                Some(zone) if is_over(zone) => Some(idx),
+                // The indexed zone covers the rounded step but not the fractional step, however no
+                // earlier zone can cover either.
+                Some(zone) if zone.fractional_step.is_some() => {
+                    let next = idx + 1;
+                    self.pairs[next..].iter().position(is_over).map(|i| next + i)
+                },
+                _ => None,
+            },
+            Some(None) => None,
+            // Steps beyond `MIN_STEP` and `MAX_STEP` are not indexed.
+            None => self.pairs.iter().position(is_over),
+        }
+    }
+
+    /// Checks the `Map` for zones that can never be played and for steps and velocities within
+    /// `MIN_STEP..MAX_STEP` and `0.0..1.0` that play nothing.
+    ///
+    /// Returns an empty `Vec` if no problems were found, making it easy to check presets within
+    /// tests, i.e. `assert_eq!(map.validate(), vec![])`.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        let num_articulations = self.num_articulations();
+        // The velocity indices built so far for each trigger and articulation.
+        let mut indices: Vec<(Trigger, usize, Vec<VelIndex>)> = vec![];
+
+        for (i, zone) in self.pairs.iter().enumerate() {
+            let StepVelRange { ref step, ref vel } = zone.range;
+            if step.min > step.max {
+                diagnostics.push(Diagnostic::InvertedStepRange { zone: i });
+            }
+            if vel.min > vel.max {
+                diagnostics.push(Diagnostic::InvertedVelRange { zone: i });
+            }
+            if step.min < MIN_STEP || step.max > MAX_STEP {
+                diagnostics.push(Diagnostic::StepOutOfRange { zone: i });
+            }
+            if step.min > step.max || vel.min > vel.max {
+                continue;
+            }
+
+            // The zone is reachable if, under any of the selections in which it is playable, it is
+            // the first zone with the same trigger at some fractional step and velocity segment, or
+            // if it crossfades with the first zone there. The zone's controllers are assumed to sit
+            // at the middle of their ranges while all other controllers keep their current values.
+            let articulations = match zone.articulation {
+                Some(a) => a..a + 1,
+                None => 0..num_articulations,
+            };
+            let (min, max) = (std::cmp::max(step.min, MIN_STEP), std::cmp::min(step.max, MAX_STEP));
+            let mut is_reachable = false;
+            for articulation in articulations {
+                let mut selection = Selection {
+                    articulation: articulation,
+                    controllers: self.selection.controllers.clone(),
+                };
+                for &(controller, ref range) in &zone.controllers {
+                    selection.set_controller(controller, range.min + (range.max - range.min) / 2.0);
+                }
+                let existing = indices.iter()
+                    .position(|&(trigger, a, _)| trigger == zone.trigger && a == articulation);
+                let idx = match existing {
+                    Some(idx) => idx,
+                    None => {
+                        let vel_indices = (MIN_STEP..MAX_STEP + 1)
+                            .map(|step| VelIndex::new(step, zone.trigger, articulation, &self.pairs))
+                            .collect();
+                        indices.push((zone.trigger, articulation, vel_indices));
+                        indices.len() - 1
+                    },
+                };
+                let vel_indices = &indices[idx].2;
+                let pairs = &self.pairs;
+                let trigger = zone.trigger;
+                is_reachable = (min..max + 1).any(|s| {
+                    self.fractional_steps(s).into_iter().any(|x| {
+                        vel_indices[(s - MIN_STEP) as usize].segments().any(|(v, _)| {
+                            let first = pairs.iter().position(|other| {
+                                other.is_in_articulation(trigger, articulation)
+                                    && other.is_selected(&selection) && other.is_over(x, v)
+                            });
+                            match first {
+                                Some(first) if first == i => true,
+                                Some(first) => zone.is_over(x, v) && zone.is_crossfading()
+                                    && pairs[first].is_crossfading(),
+                                None => false,
+                            }
+                        })
+                    })
+                });
+                if is_reachable {
+                    break;
+                }
+            }
+            if min <= max && !is_reachable {
+                diagnostics.push(Diagnostic::Hidden { zone: i });
+            }
+        }
+
+        // Find the gaps within each step, merging those that are equal across consecutive steps.
+        // A gap of `None` indicates that nothing is covered at any velocity. Only the `Attack` zones
+        // playable under the current articulation and controller values are considered, as
+        // release samples are rarely recorded for every note.
+        let mut open: Vec<(Step, Option<Range<Velocity>>)> = vec![];
+        for s in MIN_STEP..MAX_STEP + 2 {
+            let gaps = if s <= MAX_STEP { self.gaps_at(s) } else { vec![] };
+            let (continued, closed): (Vec<_>, Vec<_>) = open.into_iter()
+                .partition(|&(_, ref gap)| gaps.contains(gap));
+            for (start, gap) in closed {
+                let steps = Range { min: start, max: s - 1 };
+                diagnostics.push(match gap {
+                    None => Diagnostic::StepGap { steps: steps },
+                    Some(vel) => Diagnostic::VelGap { steps: steps, vel: vel },
+                });
+            }
+            open = continued;
+            for gap in gaps {
+                if !open.iter().any(|&(_, ref open_gap)| *open_gap == gap) {
+                    open.push((s, gap));
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    /// A fractional step from each span of the fractional steps that round to the given step,
+    /// over which the zones covering the step remain the same.
+    ///
+    /// This is just the step itself unless some microtonal zone begins or ends within it. Fractional
+    /// steps beyond `MIN_STEP` and `MAX_STEP` are ignored.
+    fn fractional_steps(&self, step: Step) -> Vec<f32> {
+        let lower = (step as f32 - 0.5).max(MIN_STEP as f32);
+        let upper = (step as f32 + 0.5).min(MAX_STEP as f32);
+        let mut bounds = vec![];
+        for zone in self.pairs.iter().filter(|zone| zone.range.step.is_over(step)) {
+            if let Some(ref fractional_step) = zone.fractional_step {
+                bounds.extend(Some(fractional_step.min).into_iter().chain(Some(fractional_step.max))
+                    .filter(|&bound| bound > lower && bound < upper));
+            }
+        }
+        if bounds.is_empty() {
+            return vec![step as f32];
+        }
+        bounds.push(lower);
+        bounds.push(upper);
+        bounds.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        bounds.dedup();
+        let between: Vec<f32> = bounds.windows(2).map(|w| w[0] + (w[1] - w[0]) / 2.0).collect();
+        bounds.into_iter()
+            .chain(between)
+            .filter(|&x| x.round() as Step == step)
+            .collect()
+    }
+
+    /// The ranges of velocity between `0.0` and `1.0` that are not covered by any playable `Attack`
+    /// zone at some fractional step rounding to the given step, including `None` if no zone covers
+    /// some fractional step at all.
+    fn gaps_at(&self, step: Step) -> Vec<Option<Range<Velocity>>> {
+        let mut gaps = vec![];
+        for x in self.fractional_steps(step) {
+            for gap in self.gaps_at_fractional_step(x) {
+                if !gaps.contains(&gap) {
+                    gaps.push(gap);
+                }
+            }
+        }
+        gaps
+    }
+
+    /// The ranges of velocity between `0.0` and `1.0` that are not covered by any playable `Attack`
+    /// zone at the given fractional step, or `vec![None]` if no zone covers the step at all.
+    fn gaps_at_fractional_step(&self, step: f32) -> Vec<Option<Range<Velocity>>> {
+        let mut covered: Vec<(Velocity, Velocity)> = self.pairs.iter()
+            .filter(|zone| zone.is_playable(Trigger::Attack, &self.selection))
+            .filter(|zone| zone.is_over_step(step) && zone.range.vel.min <= zone.range.vel.max)
+            .map(|zone| (zone.range.vel.min, zone.range.vel.max))
+            .collect();
+        if covered.is_empty() {
+            return vec![None];
+        }
+        covered.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut gaps = vec![];
+        let mut reached = 0.0;
+        for (min, max) in covered {
+            if reached >= 1.0 {
+                break;
+            }
+            if min > reached {
+                gaps.push(Some(Range { min: reached, max: min.min(1.0) }));
+            }
+            reached = max.max(reached);
+        }
+        if reached < 1.0 {
+            gaps.push(Some(Range { min: reached, max: 1.0 }));
+        }
+        gaps
+    }
+
+    /// Shifts the step range of every zone by the given number of steps.
+    ///
+    /// The `base_hz` of each sample is left unchanged, so each zone is played at the pitch of its
+    /// new steps. The `key_switches` are also left unchanged.
+    pub fn transpose(&mut self, steps: Step) {
+        for zone in &mut self.pairs {
+            zone.range.step.min = zone.range.step.min.saturating_add(steps);
+            zone.range.step.max = zone.range.step.max.saturating_add(steps);
+            if let Some(ref mut fractional_step) = zone.fractional_step {
+                fractional_step.min += steps as f32;
+                fractional_step.max += steps as f32;
+            }
+        }
+        self.reindex();
+    }
+
+    /// Combines the zones of the `other` map with the zones of this map, ordering them according
+    /// to the given `priority`.
+    ///
+    /// The `key_switches` of the `other` map are only used if this map has none. The articulation
+    /// and controller values of this map are kept.
+    pub fn merge(&mut self, other: Map<A>, priority: Priority) {
+        let Map { mut pairs, key_switches, .. } = other;
+        // The merged zones are given new ids so as not to collide with those of this map.
+        for zone in &mut pairs {
+            zone.id = Some(self.new_id());
+        }
+        match priority {
+            Priority::Existing => self.pairs.extend(pairs),
+            Priority::Merged => {
+                let existing = std::mem::replace(&mut self.pairs, pairs);
+                self.pairs.extend(existing);
+            },
+            Priority::Range => for zone in pairs {
+                let idx = self.pairs.iter()
+                    .position(|pair| pair.range > zone.range)
+                    .unwrap_or(self.pairs.len());
+                self.pairs.insert(idx, zone);
+            },
+        }
+        if self.key_switches.is_none() {
+            self.key_switches = key_switches;
+        }
+        self.reindex();
+    }
+
+    /// Restricts the map to the given window of steps and velocities.
+    ///
+    /// The range of each zone is clipped to the window, and zones that lie entirely outside of the
+    /// window are removed. The `fractional_step` range of a microtonal zone is clipped to the
+    /// fractional steps that round to the window's steps.
+    pub fn restrict(&mut self, window: StepVelRange) {
+        self.pairs.retain(|zone| zone.range.overlaps(&window));
+        let StepVelRange { step, vel } = window;
+        for zone in &mut self.pairs {
+            let range = &mut zone.range;
+            range.step.min = std::cmp::max(range.step.min, step.min);
+            range.step.max = std::cmp::min(range.step.max, step.max);
+            range.vel.min = range.vel.min.max(vel.min);
+            range.vel.max = range.vel.max.min(vel.max);
+            if let Some(ref mut fractional_step) = zone.fractional_step {
+                fractional_step.min = fractional_step.min.max(step.min as f32 - 0.5);
+                fractional_step.max = fractional_step.max.min(step.max as f32 + 0.5);
+            }
+        }
+        self.reindex();
+    }
+
+    /// Removes every zone for which the given predicate returns `false`.
+    pub fn retain<F>(&mut self, f: F)
+        where F: FnMut(&SampleOverRange<A>) -> bool,
+    {
+        self.pairs.retain(f);
+        self.reindex();
+    }
+
+    /// Maps the `Map` with some `Audio` type `A` to a `Map` with some `Audio` type `B`, applying
+    /// the given function to the audio of every sample within every zone.
+    pub fn map_audio<F, B>(self, mut map: F) -> Map<B>
+        where F: FnMut(A) -> B,
+    {
+        let Map { pairs, next_id, key_switches, choke_fade, selection, indices } = self;
+        let pairs = pairs.into_iter().map(|zone| zone.map_audio(&mut map)).collect();
+        Map {
+            pairs: pairs,
+            next_id: next_id,
+            key_switches: key_switches,
+            choke_fade: choke_fade,
+            selection: selection,
+            indices: indices,
+        }
+    }
+
+
+}
+
+impl<A> Map<A>
+    where A: Audio,
+{
+
+    /// Construct an empty `Map`.
+    pub fn empty() -> Self {
+        Map::from_pairs(vec![])
+    }
+
+    /// Construct a `Map` from a series of mappings, starting from (-C2, 1.0).
+    pub fn from_sequential_steps<I>(mappings: I) -> Self
+        where I: IntoIterator<Item=(Step, Velocity, Sample<A>)>,
+    {
+        let (mut last_step, mut last_vel) = (0, 1.0);
+        let pairs = mappings.into_iter().map(|(step, vel, sample)| {
+            let range = StepVelRange {
+                step: Range { min: last_step, max: step },
+                vel: Range { min: last_vel, max: vel },
+            };
+            last_step = step;
+            last_vel = vel;
+            SampleOverRange::new(range, sample)
+        }).collect();
+        Map::from_pairs(pairs)
+    }
+
+    /// Construct a `Map` that spreads the given samples across the entire Step range, giving each
+    /// sample the range of steps nearest to the root step of its `base_hz`.
+    ///
+    /// The `split` determines where the boundary between samples with neighbouring roots is
+    /// placed. Each sample covers the entire velocity range. Samples that share a root step also
+    /// share a step range, in which case the sample with the lowest `base_hz` is played.
+    pub fn from_pitched_samples<I>(samples: I, split: Split) -> Self
+        where I: IntoIterator<Item=Sample<A>>,
+    {
+        let mut samples: Vec<(Step, Sample<A>)> = samples.into_iter()
+            .map(|sample| (sample.base_hz.step().round() as Step, sample))
+            .collect();
+        samples.sort_by(|a, b| {
+            a.1.base_hz.hz().partial_cmp(&b.1.base_hz.hz()).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let mut roots: Vec<Step> = samples.iter().map(|&(root, _)| root).collect();
+        roots.dedup();
+
+        let pairs = samples.into_iter().map(|(root, sample)| {
+            let range = StepVelRange {
+                step: spread_step_range(&roots, root, split),
+                vel: Range { min: 0.0, max: 1.0 },
+            };
+            SampleOverRange::new(range, sample)
+        }).collect();
+
+        Map::from_pairs(pairs)
+    }
+
+    /// Creates a `Map` with a single sample mapped to the entire Step and Velocity range.
+    pub fn from_single_sample(sample: Sample<A>) -> Self {
+        let range = StepVelRange {
+            step: Range { min: MIN_STEP, max: MAX_STEP },
+            vel: Range { min: 0.0, max: 1.0 },
+        };
+        let pairs = vec![SampleOverRange::new(range, sample)];
+        Map::from_pairs(pairs)
+    }
+
+    /// Inserts a range -> audio mapping into the Map.
+    pub fn insert(&mut self, range: StepVelRange, sample: Sample<A>) {
+        self.insert_zone(SampleOverRange::new(range, sample));
+    }
+
+    /// Inserts a range -> audio mapping where the given `sample` takes turns with each of the
+    /// `alternates` on successive `note_on`s in the manner described by `alternation`.
+    pub fn insert_group<I>(&mut self,
+                           range: StepVelRange,
+                           sample: Sample<A>,
+                           alternates: I,
+                           alternation: Alternation)
+        where I: IntoIterator<Item=Sample<A>>,
+    {
+        let mut zone = SampleOverRange::new(range, sample);
+        zone.alternates = alternates.into_iter().collect();
+        zone.alternation = alternation;
+        self.insert_zone(zone);
+    }
+
+    /// Inserts a mapping from the given range of fractional steps to the given sample.
+    pub fn insert_fractional(&mut self, step: Range<f32>, vel: Range<Velocity>, sample: Sample<A>) {
+        self.insert_zone(SampleOverRange::fractional(step, vel, sample));
+    }
+
+    /// Inserts a mapping from the given range of frequencies to the given sample.
+    pub fn insert_hz(&mut self, hz: Range<pitch::Hz>, vel: Range<Velocity>, sample: Sample<A>) {
+        self.insert_zone(SampleOverRange::hz(hz, vel, sample));
+    }
+
+    /// Inserts a range -> audio mapping that is only played while the given articulation is active.
+    pub fn insert_articulation(&mut self, articulation: usize, range: StepVelRange, sample: Sample<A>) {
+        let mut zone = SampleOverRange::new(range, sample);
+        zone.articulation = Some(articulation);
+        self.insert_zone(zone);
+    }
+
+    /// Inserts a range -> audio mapping that is only played while the value of each of the given
+    /// controllers lies within its paired range.
+    pub fn insert_controlled<I>(&mut self, range: StepVelRange, controllers: I, sample: Sample<A>)
+        where I: IntoIterator<Item=(Controller, Range<f32>)>,
+    {
+        let mut zone = SampleOverRange::new(range, sample);
+        zone.controllers = controllers.into_iter().collect();
+        self.insert_zone(zone);
+    }
+
+    /// Inserts a range -> audio mapping that is played upon `note_off` rather than `note_on`.
+    ///
+    /// See `SampleOverRange::release_half_life` for a description of `half_life`.
+    pub fn insert_release(&mut self,
+                          range: StepVelRange,
+                          sample: Sample<A>,
+                          half_life: Option<time::Ms>)
+    {
+        self.insert_zone(SampleOverRange::release(range, sample, half_life));
+    }
+
+    /// Inserts a range -> audio mapping whose notes choke, and are choked by, the notes of every
+    /// other zone within the given `choke_group`.
+    pub fn insert_choked(&mut self, choke_group: u32, range: StepVelRange, sample: Sample<A>) {
+        let mut zone = SampleOverRange::new(range, sample);
+        zone.choke_group = Some(choke_group);
+        self.insert_zone(zone);
+    }
+
+    /// Inserts the given zone into the Map, maintaining the order of the `pairs` by range.
+    ///
+    /// Returns the `ZoneId` given to the zone, replacing any `id` that it already had.
+    pub fn insert_zone(&mut self, mut zone: SampleOverRange<A>) -> ZoneId {
+        let zone_articulation = zone.articulation;
+        let idx = self.pairs.iter()
+            .position(|pair| pair.range > zone.range)
+            .unwrap_or(self.pairs.len());
+        let id = self.new_id();
+        zone.id = Some(id);
+        self.pairs.insert(idx, zone);
+        // A zone of a new articulation requires a new index.
+        let last = self.indices.len() - 1;
+        if zone_articulation.map_or(false, |a| a >= last) {
+            self.reindex();
+            return id;
+        }
+        for (articulation, index) in self.indices.iter_mut().enumerate() {
+            index.insert(idx, &self.pairs, articulation);
+        }
+        self.reserve_controllers();
+        id
+    }
+
+    /// Returns the `Audio` associated with the range within which the given hz and velocity exist.
+    ///
+    /// If the matching zone has `alternates`, its `alternation` is advanced so that the following
+    /// call may return the next sample in the group. The `rng` is used by `Random` alternation.
+    ///
+    /// This clones the `Sample`. See `Map::sample_idx` for a lookup that does not.
+    pub fn sample<R>(&mut self, hz: pitch::Hz, vel: Velocity, rng: &mut R) -> Option<Sample<A>>
+        where R: rand::Rng,
+    {
+        self.sample_idx(hz, vel, rng).and_then(|idx| self.get_sample(idx)).cloned()
+    }
+
+    /// Returns the index of the `Sample` associated with the range within which the given hz and
+    /// velocity exist, advancing the zone's `alternation` as in `Map::sample`.
+    ///
+    /// This neither allocates nor clones the `Sample`, making it suitable for use on the audio
+    /// thread.
+    pub fn sample_idx<R>(&mut self, hz: pitch::Hz, vel: Velocity, rng: &mut R) -> Option<SampleIdx>
+        where R: rand::Rng,
+    {
+        let step_f = hz.step();
+        let step = step_f.round() as Step;
+        self.fractional_zone_idx(step_f, vel).map(|zone| {
+            let sample = self.pairs[zone].next_sample_idx(step, rng);
+            SampleIdx { zone: zone, id: self.pairs[zone].id, sample: sample }
+        })
+    }
+
+    /// Returns each sample that should be layered to play the given hz and velocity, paired with
+    /// the gain at which it should be played.
+    ///
+    /// The first zone within which the hz and velocity exist is always played. If that zone has a
+    /// `vel_crossfade` or `step_crossfade`, every other crossfading zone within which the hz and
+    /// velocity exist is also played (up to `MAX_LAYERS`), with gains determined by where the
+    /// velocity and the (fractional) step of the hz sit within their overlaps.
+    ///
+    /// The `alternation` of each zone that is played is advanced as in `Map::sample`.
+    ///
+    /// This clones each `Sample`. See `Map::layer_indices` for a lookup that does not.
+    pub fn layers<R>(&mut self, hz: pitch::Hz, vel: Velocity, rng: &mut R) -> Vec<(Sample<A>, f32)>
+        where R: rand::Rng,
+    {
+        let layers = self.layer_indices(hz, vel, rng);
+        self.cloned_layers(&layers)
+    }
+
+    /// The indices of the samples returned by `Map::layers`.
+    ///
+    /// This neither allocates nor clones any `Sample`, making it suitable for use on the audio
+    /// thread.
+    pub fn layer_indices<R>(&mut self, hz: pitch::Hz, vel: Velocity, rng: &mut R) -> Layers
+        where R: rand::Rng,
+    {
+        let first = self.fractional_zone_idx(hz.step(), vel);
+        self.layers_from(first, Trigger::Attack, hz, vel, None, rng)
+    }
+
+    /// Returns each sample that should be layered upon the release of a note of the given hz and
+    /// velocity that was held for the given duration, paired with the gain at which it should be
+    /// played.
+    ///
+    /// Layers are selected from the `Release` zones in the same manner as `Map::layers`. The gain
+    /// of each is further attenuated by its zone's `release_half_life`.
+    pub fn release_layers<R>(&mut self,
+                             hz: pitch::Hz,
+                             vel: Velocity,
+                             held: time::Ms,
+                             rng: &mut R) -> Vec<(Sample<A>, f32)>
+        where R: rand::Rng,
+    {
+        let layers = self.release_layer_indices(hz, vel, held, rng);
+        self.cloned_layers(&layers)
+    }
+
+    /// The indices of the samples returned by `Map::release_layers`.
+    ///
+    /// This neither allocates nor clones any `Sample`.
+    pub fn release_layer_indices<R>(&mut self,
+                                    hz: pitch::Hz,
+                                    vel: Velocity,
+                                    held: time::Ms,
+                                    rng: &mut R) -> Layers
+        where R: rand::Rng,
+    {
+        let step_f = hz.step();
+        let selection = &self.selection;
+        let first = self.pairs.iter()
+            .position(|zone| zone.is_playable(Trigger::Release, selection) && zone.is_over(step_f, vel));
+        self.layers_from(first, Trigger::Release, hz, vel, Some(held), rng)
+    }
+
+    /// A clone of each of the given layers' samples, paired with its gain.
+    fn cloned_layers(&self, layers: &Layers) -> Vec<(Sample<A>, f32)> {
+        layers.iter()
+            .filter_map(|&(idx, gain)| self.get_sample(idx).map(|sample| (sample.clone(), gain)))
+            .collect()
+    }
+
+    /// The layers played for the given hz and velocity, beginning with the zone at index `first`
+    /// and crossfading with the following zones played upon the same `trigger` within the active
+    /// articulation.
+    ///
+    /// If `held` is given, each layer is attenuated by its zone's `release_gain`.
+    fn layers_from<R>(&mut self,
+                      first: Option<usize>,
+                      trigger: Trigger,
+                      hz: pitch::Hz,
+                      vel: Velocity,
+                      held: Option<time::Ms>,
+                      rng: &mut R) -> Layers
+        where R: rand::Rng,
+    {
+        let step_f = hz.step();
+        let step = step_f.round() as Step;
+        let selection = &self.selection;
+        let is_over = |zone: &SampleOverRange<A>| {
+            zone.is_playable(trigger, selection) && zone.is_over(step_f, vel)
+        };
+
+        let mut layers = Layers::new();
+        let first = match first {
+            Some(idx) => idx,
+            None => return layers,
+        };
+
+        if !self.pairs[first].is_crossfading() {
+            let gain = held.map_or(1.0, |held| self.pairs[first].release_gain(held));
+            let sample = self.pairs[first].next_sample_idx(step, rng);
+            layers.push(SampleIdx { zone: first, id: self.pairs[first].id, sample: sample }, gain);
+            return layers;
+        }
+
+        // Collect the crossfading zones without allocating.
+        let mut crossfading = [0; MAX_LAYERS];
+        let mut len = 0;
+        for i in first..self.pairs.len() {
+            if len == MAX_LAYERS {
+                break;
+            }
+            if is_over(&self.pairs[i]) && self.pairs[i].is_crossfading() {
+                crossfading[len] = i;
+                len += 1;
+            }
+        }
+        let crossfading = &crossfading[..len];
+
+        for &i in crossfading {
+            let gain = {
+                let pairs = &self.pairs;
+                let zone = &pairs[i];
+                let others = || crossfading.iter().filter(|&&j| j != i).map(|&j| &pairs[j]);
+                let mut gain = 1.0;
+                if let Some(crossfade) = zone.vel_crossfade {
+                    let neighbours = others()
+                        .filter(|other| other.vel_crossfade.is_some())
+                        .map(|other| (other.range.vel.min, other.range.vel.max));
+                    gain *= crossfade.gain(zone.range.vel.min, zone.range.vel.max, neighbours, vel);
+                }
+                if let Some(crossfade) = zone.step_crossfade {
+                    let neighbours = others()
+                        .filter(|other| other.step_crossfade.is_some())
+                        .map(|other| other.step_bounds());
+                    let (min, max) = zone.step_bounds();
+                    gain *= crossfade.gain(min, max, neighbours, step_f);
+                }
+                if let Some(held) = held {
+                    gain *= zone.release_gain(held);
+                }
+                gain
+            };
+            // Skip layers that are silent at the very edge of an overlap.
+            if gain > 0.0 {
+                let sample = self.pairs[i].next_sample_idx(step, rng);
+                layers.push(SampleIdx { zone: i, id: self.pairs[i].id, sample: sample }, gain);
+            }
+        }
+        layers
+    }
+
+    /// Resets the `alternation` of every zone so that each begins again from its first sample.
+    pub fn reset_alternation(&mut self) {
+        for zone in &mut self.pairs {
+            zone.alternation.reset();
+        }
+    }
+
+}
+
+
+/// The number of articulations referred to by the given zones, which is always at least `1`.
+fn num_articulations<A>(pairs: &[SampleOverRange<A>]) -> usize {
+    pairs.iter().filter_map(|zone| zone.articulation).max().map_or(1, |a| a + 1)
+}
+
+/// The range of steps nearest to the given `root` out of the sorted, de-duplicated `roots`.
+///
+/// The lowest and highest roots are extended to `MIN_STEP` and `MAX_STEP` respectively.
+fn spread_step_range(roots: &[Step], root: Step, split: Split) -> Range<Step> {
+    let i = roots.iter().position(|&r| r == root).unwrap_or(0);
+    let min = match i {
+        0 => MIN_STEP,
+        i => split.boundary(roots[i - 1], root) + 1,
+    };
+    let max = match roots.get(i + 1) {
+        Some(&upper) => split.boundary(root, upper),
+        None => MAX_STEP,
+    };
+    Range { min: min, max: max }
+}
+
+/// The `base_hz` of a sample loaded from the file at the given `path`.
+///
+/// If the file name has a musical note in it, that note's playback frequency in `hz` is used. See
+/// `note::find` for how the note is found. Note names are read with middle C named `C4`.
+///
+/// If a musical note cannot be determined automatically, a default `C1` is used.
+#[cfg(any(feature="wav", feature="flac"))]
+fn base_hz_from_path(path: &std::path::Path) -> pitch::Hz {
+    use note;
+
+    const DEFAULT_LETTER_OCTAVE: pitch::LetterOctave = pitch::LetterOctave(pitch::Letter::C, 1);
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| note::find(stem, note::MiddleC::C4))
+        .map_or(DEFAULT_LETTER_OCTAVE.to_hz(), |step| pitch::Step(step as f32).to_hz())
+}
+
+
+#[cfg(feature="serde_serialization")]
+pub mod private {
+    use super::{Map, ZoneId};
+
+    /// The `ZoneId` with the given value, for use within serde.rs.
+    pub fn zone_id(value: u64) -> ZoneId {
+        ZoneId(value)
+    }
+
+    /// The value of the given `ZoneId`, for use within serde.rs.
+    pub fn zone_id_value(id: ZoneId) -> u64 {
+        id.0
+    }
+
+    /// The id given to the next zone added to the `map`, for use within serde.rs.
+    pub fn next_id<A>(map: &Map<A>) -> u64 {
+        map.next_id
+    }
+
+    /// Ensures that no id lower than `next_id` is given to a zone added to the `map`, for use
+    /// within serde.rs.
+    pub fn reserve_ids<A>(map: &mut Map<A>, next_id: u64) {
+        map.next_id = std::cmp::max(map.next_id, next_id);
+    }
+}
+
+
+#[cfg(feature="wav")]
+pub mod wav {
+    use audio;
+    use map;
+    use naming;
+    use pitch;
+    use sample;
+    use std;
+    use {Step, Velocity};
+
+
+    /// An alias for the `wav` `Sample` type.
+    pub type Sample<F> = super::Sample<std::sync::Arc<audio::wav::Audio<F>>>;
+
+    /// Errors that may occur while loading a `Map` from a directory of `.wav` files.
+    #[derive(Debug)]
+    pub enum Error {
+        /// Some error that occurred while reading the directory.
+        Io(std::io::Error),
+        /// Some error that occurred while loading one of the `.wav` files.
+        Wav(audio::wav::Error),
+    }
+
+
+    impl<F> Sample<F>
+        where F: sample::Frame,
+              F::Sample: sample::Duplex<f64> + sample::Duplex<i32>,
+              Box<[F::Sample]>: sample::ToBoxedFrameSlice<F>,
+    {
+
+        /// Loads a `Sample` from the `.wav` file at the given `path`.
+        ///
+        /// If the `.wav` file has a musical note in the file name, that note's playback frequency in
+        /// `hz` will be used as the `base_hz`. See `note::find` for how the note is found. Note
+        /// names are read with middle C named `C4`.
+        ///
         /// If a musical note cannot be determined automatically, a default `C1` will be used.
         ///
         /// The PCM data retrieved from the file will be re-sampled upon loading (rather than at
@@ -173,10 +1962,7 @@ pub mod wav {
             where P: AsRef<std::path::Path>,
         {
             let path = path.as_ref();
-
-            const DEFAULT_LETTER_OCTAVE: pitch::LetterOctave = pitch::LetterOctave(pitch::Letter::C, 1);
-            let base_letter_octave = read_base_letter_octave(path).unwrap_or(DEFAULT_LETTER_OCTAVE);
-            let base_hz = base_letter_octave.to_hz();
+            let base_hz = map::base_hz_from_path(path);
             let base_vel = 1.0;
 
             let audio = std::sync::Arc::new(try!(audio::wav::Audio::from_file(path, target_sample_hz)));
@@ -185,53 +1971,784 @@ pub mod wav {
         }
     }
 
+    impl<F> map::Map<std::sync::Arc<audio::wav::Audio<F>>>
+        where F: sample::Frame,
+              F::Sample: sample::Duplex<f64> + sample::Duplex<i32>,
+              Box<[F::Sample]>: sample::ToBoxedFrameSlice<F>,
+    {
 
-    /// Scans the given path for an indication of its pitch.
-    fn read_base_letter_octave(path: &std::path::Path) -> Option<pitch::LetterOctave> {
-        use pitch::Letter::*;
-        use std::ascii::AsciiExt;
-
-        let s = path.to_str().map_or("".into(), |s| s.to_ascii_lowercase());
-
-        // Check to see if the path contains a note for the given `letter` for any octave
-        // between -8 and 24. If so, return the `LetterOctave`.
-        let contains_letter = |letter: &str| -> Option<pitch::LetterOctave> {
-            for i in -8i8..24 {
-                let pattern = format!("{}{}", letter, i);
-                if s.contains(&pattern) {
-                    let letter = match letter {
-                        "c" => C,
-                        "c#" | "csh" => Csh,
-                        "d" => D,
-                        "d#" | "dsh" => Dsh,
-                        "e" => E,
-                        "f" => F,
-                        "f#" | "fsh" => Fsh,
-                        "g" => G,
-                        "g#" | "gsh" => Gsh,
-                        "a" => A,
-                        "a#" | "ash" => Ash,
-                        "b" => B,
-                        _ => unreachable!(),
-                    };
-                    return Some(pitch::LetterOctave(letter, i as pitch::Octave));
-                }
-            }
-            None
-        };
+        /// Loads every `.wav` file within the given directory into a `Map`, reading where each
+        /// sample belongs from its file name using the first of the given `patterns` that matches.
+        ///
+        /// Files that match none of the `patterns` are ignored.
+        ///
+        /// - The `{root}` of a file is used as its `base_hz`. Files without a `{root}` fall back
+        /// to the note found by `Sample::from_wav_file`.
+        /// - Key ranges are given by `{low}` and `{high}`. Any bound that is missing is found by
+        /// spreading the roots of each velocity layer across the steps as described by `split`.
+        /// - Velocity ranges are given by `{vel}` or, failing that, by dividing the velocity range
+        /// evenly between each `{layer}` sharing the same key range. Files with neither cover the
+        /// entire velocity range. The top of each file's velocity range is used as its `base_vel`.
+        /// - Files that share both a key range and a velocity range form a round-robin group,
+        /// ordered by `{rr}`.
+        pub fn from_wav_directory<P>(path: P,
+                                     patterns: &[naming::Pattern],
+                                     split: map::Split,
+                                     target_sample_hz: f64) -> Result<Self, Error>
+            where P: AsRef<std::path::Path>,
+        {
+            struct Entry<F> {
+                fields: naming::Fields,
+                root: Step,
+                sample: Sample<F>,
+            }
 
-        let list = [
-            "c", "c#", "csh", "d", "d#", "dsh", "e", "f", "f#", "fsh", "g", "g#", "gsh",
-            "a", "a#", "ash", "b",
-        ];
+            let mut paths = Vec::new();
+            for entry in try!(std::fs::read_dir(path)) {
+                let path = try!(entry).path();
+                let is_wav = path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .map_or(false, |ext| ext.eq_ignore_ascii_case("wav"));
+                if is_wav && path.is_file() {
+                    paths.push(path);
+                }
+            }
+            paths.sort();
 
-        for letter in &list[..] {
-            if let Some(letter_octave) = contains_letter(letter) {
-                return Some(letter_octave);
+            let mut entries = Vec::new();
+            for path in paths {
+                let fields = match patterns.iter().filter_map(|p| p.parse_path(&path)).next() {
+                    Some(fields) => fields,
+                    None => continue,
+                };
+                let mut sample = try!(Sample::from_wav_file(&path, target_sample_hz));
+                if let Some(root) = fields.root {
+                    sample.base_hz = pitch::Step(root as f32).to_hz();
+                }
+                let root = sample.base_hz.step().round() as Step;
+                entries.push(Entry { fields: fields, root: root, sample: sample });
+            }
+
+            // Velocity layers are distinguished by whichever velocity fields the file name had.
+            let layer = |fields: &naming::Fields| (fields.vel, fields.layer);
+
+            let steps: Vec<map::Range<Step>> = entries.iter().map(|entry| {
+                let mut roots: Vec<Step> = entries.iter()
+                    .filter(|other| layer(&other.fields) == layer(&entry.fields))
+                    .map(|other| other.root)
+                    .collect();
+                roots.sort();
+                roots.dedup();
+                let spread = map::spread_step_range(&roots, entry.root, split);
+                map::Range {
+                    min: entry.fields.low.unwrap_or(spread.min),
+                    max: entry.fields.high.unwrap_or(spread.max),
+                }
+            }).collect();
+
+            let vels: Vec<map::Range<Velocity>> = entries.iter().zip(&steps).map(|(entry, step)| {
+                let same_keys = || entries.iter().zip(&steps)
+                    .filter(move |&(_, other)| other == step)
+                    .map(|(other, _)| &other.fields);
+                match (entry.fields.vel, entry.fields.layer) {
+                    (Some(vel), _) => {
+                        let lower = same_keys()
+                            .filter_map(|fields| fields.vel)
+                            .filter(|&other| other < vel)
+                            .max();
+                        map::Range {
+                            min: lower.map_or(0.0, |lower| lower as Velocity / 127.0),
+                            max: vel as Velocity / 127.0,
+                        }
+                    },
+                    (None, Some(layer)) => {
+                        let mut layers: Vec<u32> = same_keys()
+                            .filter(|fields| fields.vel.is_none())
+                            .filter_map(|fields| fields.layer)
+                            .collect();
+                        layers.sort();
+                        layers.dedup();
+                        let n = layers.len() as Velocity;
+                        let i = layers.iter().position(|&l| l == layer).unwrap_or(0) as Velocity;
+                        map::Range { min: i / n, max: (i + 1.0) / n }
+                    },
+                    (None, None) => map::Range { min: 0.0, max: 1.0 },
+                }
+            }).collect();
+
+            // Group together the samples that share a zone, in round-robin order.
+            let mut groups: Vec<(map::StepVelRange, Vec<(u32, Sample<F>)>)> = Vec::new();
+            for ((entry, step), vel) in entries.into_iter().zip(steps).zip(vels) {
+                // Each sample was recorded at the loudest velocity of its layer.
+                let sample = map::Sample { base_vel: vel.max, ..entry.sample };
+                let range = map::StepVelRange { step: step, vel: vel };
+                let rr = entry.fields.rr.unwrap_or(0);
+                match groups.iter().position(|&(ref r, _)| *r == range) {
+                    Some(i) => groups[i].1.push((rr, sample)),
+                    None => groups.push((range, vec![(rr, sample)])),
+                }
+            }
+
+            let mut map = map::Map::empty();
+            for (range, mut group) in groups {
+                group.sort_by(|a, b| a.0.cmp(&b.0));
+                let mut samples = group.into_iter().map(|(_, sample)| sample);
+                if let Some(sample) = samples.next() {
+                    map.insert_group(range, sample, samples, map::Alternation::round_robin());
+                }
+            }
+            Ok(map)
+        }
+
+    }
+
+    impl From<std::io::Error> for Error {
+        fn from(err: std::io::Error) -> Self {
+            Error::Io(err)
+        }
+    }
+
+    impl From<audio::wav::Error> for Error {
+        fn from(err: audio::wav::Error) -> Self {
+            Error::Wav(err)
+        }
+    }
+
+    impl std::error::Error for Error {
+        fn description(&self) -> &str {
+            match *self {
+                Error::Io(ref io) => std::error::Error::description(io),
+                Error::Wav(ref wav) => std::error::Error::description(wav),
             }
         }
+    }
 
-        None
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+            std::fmt::Debug::fmt(self, f)
+        }
     }
 
 }
+
+
+#[cfg(feature="flac")]
+pub mod flac {
+    use audio;
+    use map;
+    use sample;
+    use std;
+
+
+    /// An alias for the `flac` `Sample` type.
+    pub type Sample<F> = super::Sample<std::sync::Arc<audio::flac::Audio<F>>>;
+
+
+    impl<F> Sample<F>
+        where F: sample::Frame,
+              F::Sample: sample::Duplex<f64> + sample::Duplex<i32>,
+              Box<[F::Sample]>: sample::ToBoxedFrameSlice<F>,
+    {
+
+        /// Loads a `Sample` from the `.flac` file at the given `path`.
+        ///
+        /// The `base_hz` is read from the file name as in `Sample::from_wav_file`.
+        ///
+        /// The PCM data decoded from the file will be re-sampled upon loading (rather than at
+        /// playback) to the given target sample rate for efficiency.
+        pub fn from_flac_file<P>(path: P, target_sample_hz: f64) -> Result<Self, audio::flac::Error>
+            where P: AsRef<std::path::Path>,
+        {
+            let path = path.as_ref();
+            let base_hz = map::base_hz_from_path(path);
+            let base_vel = 1.0;
+
+            let audio = std::sync::Arc::new(try!(audio::flac::Audio::from_file(path, target_sample_hz)));
+
+            Ok(map::Sample::new(base_hz, base_vel, audio))
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+fn test_zone(step: (Step, Step), vel: (Velocity, Velocity)) -> SampleOverRange<()> {
+    let range = StepVelRange {
+        step: Range { min: step.0, max: step.1 },
+        vel: Range { min: vel.0, max: vel.1 },
+    };
+    SampleOverRange::new(range, Sample::new(pitch::Hz(440.0), 1.0, ()))
+}
+
+#[test]
+fn test_zone_idx() {
+    // Asserts that the indexed lookup finds the same zone as a scan for the first match.
+    fn assert_first_match(map: &Map<()>) {
+        let mut vels: Vec<Velocity> = (0..21).map(|i| i as Velocity / 20.0).collect();
+        vels.extend(&[-0.1, 0.33, 0.6, 1.1, std::f32::NAN]);
+        for step in MIN_STEP - 2..MAX_STEP + 3 {
+            for &vel in &vels {
+                let first = map.pairs.iter()
+                    .position(|zone| zone.range.step.is_over(step) && zone.range.vel.is_over(vel));
+                assert_eq!(map.zone_idx(step, vel), first, "step {}, vel {}", step, vel);
+            }
+        }
+    }
+
+    let zones = vec![
+        test_zone((10, 40), (0.0, 0.5)),
+        // Overlaps the previous zone in both dimensions.
+        test_zone((30, 60), (0.25, 0.75)),
+        // Shares the previous zones' boundaries.
+        test_zone((40, 40), (0.5, 1.0)),
+        test_zone((60, 90), (0.75, 1.0)),
+        // Inverted ranges.
+        test_zone((70, 50), (0.0, 1.0)),
+        test_zone((20, 30), (0.9, 0.1)),
+        // Extends beyond `MIN_STEP` and `MAX_STEP`.
+        test_zone((-10, 5), (0.0, 1.0)),
+        test_zone((120, 300), (0.2, 1.0)),
+        test_zone((0, 127), (std::f32::NAN, 1.0)),
+        test_zone((0, 127), (0.6, 0.6)),
+        // Identical to an earlier zone, so only played where that zone is not.
+        test_zone((30, 60), (0.25, 0.75)),
+        test_zone((0, 127), (0.0, 1.0)),
+    ];
+
+    // Incrementally indexed insertions.
+    let mut map = Map::empty();
+    for zone in zones.iter().cloned() {
+        map.insert_zone(zone);
+        assert_first_match(&map);
+    }
+    map.reindex();
+    assert_first_match(&map);
+
+    // The same zones inserted in reverse.
+    let mut map = Map::empty();
+    for zone in zones.iter().rev().cloned() {
+        map.insert_zone(zone);
+        assert_first_match(&map);
+    }
+    map.reindex();
+    assert_first_match(&map);
+
+    // Zones given in an order of priority other than that of their ranges.
+    assert_first_match(&Map::from_pairs(zones.clone()));
+    assert_first_match(&Map::from_pairs(zones.into_iter().rev().collect()));
+}
+
+#[test]
+fn test_set_controller() {
+    let mod_wheel = Controller::Cc(1);
+    let mut map = Map::empty();
+    let mut zones = vec![
+        test_zone((0, 127), (0.0, 1.0)),
+        test_zone((0, 60), (0.0, 0.5)),
+        test_zone((40, 127), (0.25, 1.0)),
+        test_zone((0, 127), (0.0, 1.0)),
+    ];
+    zones[1].controllers = vec![(mod_wheel, Range { min: 0.0, max: 0.5 })];
+    zones[2].controllers = vec![(mod_wheel, Range { min: 0.5, max: 1.0 }),
+                                (Controller::Aftertouch, Range { min: 0.0, max: 0.5 })];
+    zones[3].controllers = vec![(mod_wheel, Range { min: 0.75, max: 1.0 })];
+    for zone in zones {
+        map.insert_zone(zone);
+    }
+    map.pairs.swap(0, 3);
+    map.reindex();
+
+    let capacity = map.selection.controllers.capacity();
+    for &(controller, value) in &[(mod_wheel, 0.0), (mod_wheel, 0.5), (Controller::Aftertouch, 0.75),
+                                  (mod_wheel, 0.8), (Controller::Aftertouch, 0.25), (mod_wheel, 1.0)] {
+        map.set_controller(controller, value);
+        for step in MIN_STEP..MAX_STEP + 1 {
+            for vel in (0..21).map(|i| i as Velocity / 20.0) {
+                let first = map.pairs.iter().position(|zone| {
+                    zone.is_playable(Trigger::Attack, &map.selection) && zone.is_over(step as f32, vel)
+                });
+                assert_eq!(map.zone_idx(step, vel), first, "step {}, vel {}", step, vel);
+            }
+        }
+    }
+    // Setting the controllers referred to by the zones does not allocate.
+    assert_eq!(map.selection.controllers.capacity(), capacity);
+    assert_eq!(map.controller(mod_wheel), 1.0);
+}
+
+#[test]
+fn test_zone_ids() {
+    let mut map = Map::empty();
+    let a = map.insert_zone(test_zone((0, 59), (0.0, 1.0)));
+    let b = map.insert_zone(test_zone((60, 127), (0.0, 1.0)));
+
+    // Ids follow their zones when the `pairs` are modified directly.
+    map.pairs.swap(0, 1);
+    let clone = map.pairs[0].clone();
+    map.pairs.push(clone);
+    map.reindex();
+    assert_eq!(map.position(a), Some(1));
+    assert_eq!(map.position(b), Some(0));
+    let c = map.zone_id(2).unwrap();
+    assert!(c != a && c != b);
+
+    // Ids are never given out twice, even once their zone has been removed.
+    map.remove(c);
+    let d = map.insert_zone(test_zone((0, 127), (0.0, 1.0)));
+    assert!(d != a && d != b && d != c);
+    assert_eq!(map.iter().map(|(id, _)| id).collect::<Vec<_>>(), vec![d, b, a]);
+
+    // Maps holding the same zones are equal regardless of their bookkeeping.
+    let mut other = Map::from_pairs(map.pairs.clone());
+    assert_eq!(map, other);
+    other.remove(d);
+    map.remove(d);
+    assert_eq!(map, other);
+}
+
+#[test]
+fn test_locate() {
+    let mut rng = rand::thread_rng();
+    let mut map = Map::empty();
+    map.insert_zone(test_zone((60, 127), (0.0, 1.0)));
+    let idx = map.sample_idx(pitch::Step(64.0).to_hz(), 1.0, &mut rng).unwrap();
+    assert_eq!(map.locate(idx), Some(idx));
+
+    // Inserting a zone ahead of the sample's zone moves it.
+    let id = map.insert_zone(test_zone((0, 59), (0.0, 1.0)));
+    assert!(map.get_sample(idx).is_none());
+    let moved = map.locate(idx).unwrap();
+    assert_eq!(moved.zone, 1);
+    assert!(map.get_sample(moved).is_some());
+
+    // Removing a zone ahead of the sample's zone moves it back.
+    map.remove(id);
+    assert_eq!(map.locate(moved), Some(idx));
+
+    // Removing the sample's own zone leaves nothing to locate.
+    map.insert_zone(test_zone((0, 59), (0.0, 1.0)));
+    map.retain(|zone| zone.range.step.min == 0);
+    assert_eq!(map.locate(idx), None);
+    assert!(map.get_sample(idx).is_none());
+}
+
+#[test]
+fn test_vel_crossfade() {
+    let mut rng = rand::thread_rng();
+    let hz = pitch::Step(60.0).to_hz();
+    let vels: Vec<Velocity> = (0..41).map(|i| i as Velocity / 40.0).collect();
+
+    for &crossfade in &[Crossfade::Linear, Crossfade::EqualPower] {
+        let mut map = Map::empty();
+        for &(min, max) in &[(0.0, 0.5), (0.3, 0.8), (0.6, 1.0)] {
+            let mut zone = test_zone((0, 127), (min, max));
+            zone.vel_crossfade = Some(crossfade);
+            map.insert_zone(zone);
+        }
+        for &vel in &vels {
+            let layers = map.layer_indices(hz, vel, &mut rng);
+            assert!(!layers.is_empty() && layers.len() <= 2, "vel {}", vel);
+            // Linear gains sum to `1.0`, while equal power gains sum to `1.0` once squared.
+            let sum: f32 = layers.iter().map(|&(_, gain)| match crossfade {
+                Crossfade::Linear => gain,
+                Crossfade::EqualPower => gain * gain,
+            }).sum();
+            assert!((sum - 1.0).abs() < 1e-5, "{:?} vel {}: {}", crossfade, vel, sum);
+        }
+    }
+
+    // Zones with identical ranges neither fade in nor out, so are layered at full gain.
+    let mut map = Map::empty();
+    for _ in 0..2 {
+        let mut zone = test_zone((0, 127), (0.0, 1.0));
+        zone.vel_crossfade = Some(Crossfade::Linear);
+        map.insert_zone(zone);
+    }
+    for &vel in &vels {
+        let layers = map.layer_indices(hz, vel, &mut rng);
+        let zones: Vec<(usize, f32)> = layers.iter().map(|&(idx, gain)| (idx.zone, gain)).collect();
+        assert_eq!(zones, vec![(0, 1.0), (1, 1.0)]);
+    }
+
+    // Crossfading zones beyond `MAX_LAYERS` are not played.
+    let mut map = Map::empty();
+    for _ in 0..MAX_LAYERS + 3 {
+        let mut zone = test_zone((0, 127), (0.0, 1.0));
+        zone.vel_crossfade = Some(Crossfade::EqualPower);
+        map.insert_zone(zone);
+    }
+    let layers = map.layer_indices(hz, 0.5, &mut rng);
+    assert_eq!(layers.len(), MAX_LAYERS);
+    let zones: Vec<usize> = layers.iter().map(|&(idx, _)| idx.zone).collect();
+    assert_eq!(zones, (0..MAX_LAYERS).collect::<Vec<_>>());
+    let mut full = Layers::new();
+    for &(idx, gain) in layers.iter() {
+        assert!(full.push(idx, gain));
+    }
+    assert!(!full.push(layers.iter().next().unwrap().0, 1.0));
+}
+
+#[test]
+fn test_step_crossfade() {
+    let mut rng = rand::thread_rng();
+    let steps: Vec<f32> = (0..127 * 4 + 1).map(|i| i as f32 / 4.0).collect();
+    let sum_of = |crossfade: Crossfade, layers: &Layers| -> f32 {
+        layers.iter().map(|&(_, gain)| match crossfade {
+            Crossfade::Linear => gain,
+            Crossfade::EqualPower => gain * gain,
+        }).sum()
+    };
+
+    for &crossfade in &[Crossfade::Linear, Crossfade::EqualPower] {
+        let mut map = Map::empty();
+        for &(min, max) in &[(0, 64), (48, 100), (90, 127)] {
+            let mut zone = test_zone((min, max), (0.0, 1.0));
+            zone.step_crossfade = Some(crossfade);
+            map.insert_zone(zone);
+        }
+        for &step in &steps {
+            let layers = map.layer_indices(pitch::Step(step).to_hz(), 1.0, &mut rng);
+            assert!(!layers.is_empty() && layers.len() <= 2, "step {}", step);
+            let sum = sum_of(crossfade, &layers);
+            assert!((sum - 1.0).abs() < 1e-4, "{:?} step {}: {}", crossfade, step, sum);
+        }
+    }
+
+    // Zones that crossfade across both steps and velocities blend all four corners of the overlap.
+    let mut map = Map::empty();
+    for &step in &[(0, 64), (48, 127)] {
+        for &vel in &[(0.0, 0.6), (0.4, 1.0)] {
+            let mut zone = test_zone(step, vel);
+            zone.step_crossfade = Some(Crossfade::Linear);
+            zone.vel_crossfade = Some(Crossfade::Linear);
+            map.insert_zone(zone);
+        }
+    }
+    for &step in &steps {
+        for vel in (0..21).map(|i| i as Velocity / 20.0) {
+            let layers = map.layer_indices(pitch::Step(step).to_hz(), vel, &mut rng);
+            let sum = sum_of(Crossfade::Linear, &layers);
+            assert!((sum - 1.0).abs() < 1e-4, "step {}, vel {}: {}", step, vel, sum);
+        }
+    }
+    let layers = map.layer_indices(pitch::Step(56.0).to_hz(), 0.5, &mut rng);
+    assert_eq!(layers.len(), 4);
+
+    // Zones with identical step ranges neither fade in nor out, so are layered at full gain.
+    let mut map = Map::empty();
+    for _ in 0..2 {
+        let mut zone = test_zone((40, 80), (0.0, 1.0));
+        zone.step_crossfade = Some(Crossfade::EqualPower);
+        map.insert_zone(zone);
+    }
+    let layers = map.layer_indices(pitch::Step(60.0).to_hz(), 1.0, &mut rng);
+    let gains: Vec<f32> = layers.iter().map(|&(_, gain)| gain).collect();
+    assert_eq!(gains, vec![1.0, 1.0]);
+}
+
+#[test]
+fn test_round_robin() {
+    let mut rng = rand::thread_rng();
+
+    // The group is cycled through in order, wrapping back around to the first sample.
+    let mut alternation = Alternation::round_robin();
+    let picks: Vec<usize> = (0..7).map(|_| alternation.next(60, 3, &mut rng)).collect();
+    assert_eq!(picks, vec![0, 1, 2, 0, 1, 2, 0]);
+    alternation.reset();
+    assert_eq!(alternation.next(60, 3, &mut rng), 0);
+
+    // Each step keeps its own counter.
+    let mut alternation = Alternation::round_robin_per_step();
+    let picks: Vec<usize> = [60, 60, 61, 60, 61, 61, 62].iter()
+        .map(|&step| alternation.next(step, 3, &mut rng))
+        .collect();
+    assert_eq!(picks, vec![0, 1, 0, 2, 1, 2, 0]);
+    alternation.reset();
+    assert_eq!(alternation.next(60, 3, &mut rng), 0);
+    assert_eq!(alternation.next(61, 3, &mut rng), 0);
+
+    // Steps beyond the counters always play the first sample.
+    assert_eq!(alternation.next(MAX_STEP + 1, 3, &mut rng), 0);
+    assert_eq!(alternation.next(MAX_STEP + 1, 3, &mut rng), 0);
+
+    // `Map::reset_alternation` resets the alternation of every zone.
+    let sample = |hz: f32| Sample::new(pitch::Hz(hz), 1.0, ());
+    let mut map = Map::empty();
+    map.insert_group(test_zone((0, 59), (0.0, 1.0)).range, sample(1.0), vec![sample(2.0)],
+                     Alternation::round_robin());
+    map.insert_group(test_zone((60, 127), (0.0, 1.0)).range, sample(3.0), vec![sample(4.0)],
+                     Alternation::round_robin_per_step());
+    let mut pick = |map: &mut Map<()>, step: f32| {
+        map.sample(pitch::Step(step).to_hz(), 1.0, &mut rng).unwrap().base_hz.hz()
+    };
+    assert_eq!(pick(&mut map, 48.0), 1.0);
+    assert_eq!(pick(&mut map, 64.0), 3.0);
+    assert_eq!(pick(&mut map, 64.0), 4.0);
+    assert_eq!(pick(&mut map, 65.0), 3.0);
+    map.reset_alternation();
+    assert_eq!(pick(&mut map, 48.0), 1.0);
+    assert_eq!(pick(&mut map, 64.0), 3.0);
+    assert_eq!(pick(&mut map, 65.0), 3.0);
+}
+
+#[test]
+fn test_random() {
+    let mut rng = rand::thread_rng();
+    for len in 2..5 {
+        let mut alternation = Alternation::random();
+        let picks: Vec<usize> = (0..1_000).map(|_| alternation.next(60, len, &mut rng)).collect();
+        assert!(picks.windows(2).all(|w| w[0] != w[1]), "len {}", len);
+        assert!((0..len).all(|idx| picks.contains(&idx)), "len {}", len);
+    }
+
+    // A group of one sample has nothing else to pick.
+    let mut alternation = Alternation::random();
+    assert!((0..10).all(|_| alternation.next(60, 1, &mut rng) == 0));
+}
+
+#[test]
+fn test_validate() {
+    let validate = |zones: Vec<SampleOverRange<()>>| Map::from_pairs(zones).validate();
+    let full = || test_zone((MIN_STEP, MAX_STEP), (0.0, 1.0));
+    let crossfading = || {
+        let mut zone = full();
+        zone.vel_crossfade = Some(Crossfade::Linear);
+        zone
+    };
+
+    assert_eq!(Map::from_single_sample(Sample::new(pitch::Hz(440.0), 1.0, ())).validate(), vec![]);
+
+    // Invalid ranges.
+    assert_eq!(validate(vec![full(), test_zone((70, 50), (0.0, 1.0))]),
+               vec![Diagnostic::InvertedStepRange { zone: 1 }]);
+    assert_eq!(validate(vec![full(), test_zone((0, 127), (0.9, 0.1))]),
+               vec![Diagnostic::InvertedVelRange { zone: 1 }]);
+    assert_eq!(validate(vec![test_zone((-10, 130), (0.0, 1.0))]),
+               vec![Diagnostic::StepOutOfRange { zone: 0 }]);
+
+    // Zones covered entirely by earlier zones.
+    assert_eq!(validate(vec![full(), test_zone((10, 20), (0.2, 0.4))]),
+               vec![Diagnostic::Hidden { zone: 1 }]);
+    assert_eq!(validate(vec![full(), full()]), vec![Diagnostic::Hidden { zone: 1 }]);
+
+    // Gaps.
+    assert_eq!(validate(vec![test_zone((0, 59), (0.0, 1.0)), test_zone((61, 127), (0.0, 1.0))]),
+               vec![Diagnostic::StepGap { steps: Range { min: 60, max: 60 } }]);
+    assert_eq!(validate(vec![test_zone((0, 127), (0.0, 0.5)),
+                             test_zone((0, 63), (0.7, 1.0)),
+                             test_zone((64, 127), (0.5, 1.0))]),
+               vec![Diagnostic::VelGap {
+                   steps: Range { min: 0, max: 63 },
+                   vel: Range { min: 0.5, max: 0.7 },
+               }]);
+    assert_eq!(validate(vec![test_zone((0, 127), (0.0, 0.5)), test_zone((0, 127), (0.5, 1.0))]),
+               vec![]);
+
+    // Zones that crossfade with the first zone over their range are played.
+    assert_eq!(validate(vec![crossfading(), crossfading()]), vec![]);
+    assert_eq!(validate(vec![crossfading(), full()]), vec![Diagnostic::Hidden { zone: 1 }]);
+
+    // Zones that are played under other articulations or controller values.
+    let articulation = |articulation| {
+        let mut zone = full();
+        zone.articulation = Some(articulation);
+        zone
+    };
+    assert_eq!(validate(vec![articulation(0), articulation(1)]), vec![]);
+    assert_eq!(validate(vec![articulation(1), full()]), vec![]);
+    let mut vibrato = full();
+    vibrato.controllers = vec![(Controller::Cc(1), Range { min: 0.5, max: 1.0 })];
+    assert_eq!(validate(vec![vibrato, full()]), vec![]);
+}
+
+#[test]
+fn test_from_pitched_samples() {
+    let sample = |step: f32| Sample::new(pitch::Step(step).to_hz(), 1.0, ());
+    let steps = |roots: &[f32], split: Split| -> Vec<(Step, Step)> {
+        let map = Map::from_pitched_samples(roots.iter().map(|&root| sample(root)), split);
+        map.pairs.iter().map(|zone| (zone.range.step.min, zone.range.step.max)).collect()
+    };
+
+    assert_eq!(steps(&[48.0, 60.0, 72.0], Split::Midpoint), vec![(0, 54), (55, 66), (67, 127)]);
+    assert_eq!(steps(&[48.0, 60.0, 72.0], Split::Upward), vec![(0, 59), (60, 71), (72, 127)]);
+    assert_eq!(steps(&[48.0, 60.0, 72.0], Split::Downward), vec![(0, 48), (49, 60), (61, 127)]);
+
+    // Samples are ordered by pitch regardless of the order in which they are given.
+    assert_eq!(steps(&[72.0, 48.0, 60.0], Split::Midpoint), vec![(0, 54), (55, 66), (67, 127)]);
+
+    // Samples sharing a root share a step range, so only the first is played.
+    let map = Map::from_pitched_samples(vec![sample(60.0), sample(48.0), sample(60.0)], Split::Midpoint);
+    let ranges: Vec<(Step, Step)> = map.pairs.iter().map(|z| (z.range.step.min, z.range.step.max)).collect();
+    assert_eq!(ranges, vec![(0, 54), (55, 127), (55, 127)]);
+    assert_eq!(map.validate(), vec![Diagnostic::Hidden { zone: 2 }]);
+
+    assert!(Map::<()>::from_pitched_samples(vec![], Split::Midpoint).pairs.is_empty());
+    assert_eq!(steps(&[60.0], Split::Upward), vec![(MIN_STEP, MAX_STEP)]);
+}
+
+#[test]
+fn test_fractional() {
+    let sample = || Sample::new(pitch::Hz(440.0), 1.0, ());
+    let vel = || Range { min: 0.0, max: 1.0 };
+    let quarter_tones = || {
+        let mut map = Map::empty();
+        for &(min, max) in &[(59.75, 60.25), (60.25, 60.75), (60.75, 61.25)] {
+            map.insert_fractional(Range { min: min, max: max }, vel(), sample());
+        }
+        map
+    };
+
+    let map = quarter_tones();
+    let steps: Vec<(Step, Step)> = map.pairs.iter().map(|z| (z.range.step.min, z.range.step.max)).collect();
+    assert_eq!(steps, vec![(60, 60), (60, 61), (61, 61)]);
+    for &(step, zone) in &[(59.7, None), (59.75, Some(0)), (60.0, Some(0)), (60.25, Some(0)),
+                           (60.3, Some(1)), (60.5, Some(1)), (60.75, Some(1)), (60.8, Some(2)),
+                           (61.25, Some(2)), (61.3, None)] {
+        assert_eq!(map.fractional_zone_idx(step, 1.0), zone, "step {}", step);
+    }
+    // Integer steps are looked up as their fractional equivalent.
+    assert_eq!(map.zone_idx(60, 1.0), Some(0));
+    assert_eq!(map.zone_idx(61, 1.0), Some(2));
+
+    // Each quarter-tone zone is played somewhere. Steps `60` and `61` are only partly covered, so
+    // are reported along with the uncovered steps either side.
+    assert_eq!(map.validate(), vec![Diagnostic::StepGap { steps: Range { min: MIN_STEP, max: MAX_STEP } }]);
+    let mut covered = quarter_tones();
+    covered.pairs.push(test_zone((MIN_STEP, MAX_STEP), (0.0, 1.0)));
+    covered.reindex();
+    assert_eq!(covered.validate(), vec![]);
+
+    // A microtonal zone covered by an earlier one is hidden.
+    let mut hidden = quarter_tones();
+    hidden.pairs.insert(1, SampleOverRange::fractional(Range { min: 60.0, max: 60.2 }, vel(), sample()));
+    hidden.pairs.push(test_zone((MIN_STEP, MAX_STEP), (0.0, 1.0)));
+    hidden.reindex();
+    assert_eq!(hidden.validate(), vec![Diagnostic::Hidden { zone: 1 }]);
+
+    // A gap between the boundaries of two microtonal zones.
+    let mut map = Map::empty();
+    map.insert_fractional(Range { min: 0.0, max: 60.25 }, vel(), sample());
+    map.insert_fractional(Range { min: 60.75, max: 127.0 }, vel(), sample());
+    assert_eq!(map.fractional_zone_idx(60.5, 1.0), None);
+    assert_eq!(map.validate(), vec![Diagnostic::StepGap { steps: Range { min: 60, max: 61 } }]);
+
+    // Zones given in hz cover the fractional steps of their bounds.
+    let hz = |step: f32| pitch::Step(step).to_hz();
+    let mut map = Map::empty();
+    map.insert_hz(Range { min: hz(68.75), max: hz(69.25) }, vel(), sample());
+    map.insert_hz(Range { min: hz(69.25), max: hz(69.75) }, vel(), sample());
+    assert_eq!(map.pairs[0].range.step, Range { min: 69, max: 69 });
+    let (min, max) = map.pairs[1].step_bounds();
+    assert!((min - 69.25).abs() < 1e-3 && (max - 69.75).abs() < 1e-3);
+    for &(step, zone) in &[(68.7, None), (68.8, Some(0)), (69.2, Some(0)), (69.3, Some(1)),
+                           (69.7, Some(1)), (69.8, None)] {
+        assert_eq!(map.sample_idx(hz(step), 1.0, &mut rand::thread_rng()).map(|idx| idx.zone), zone,
+                   "step {}", step);
+    }
+}
+
+#[test]
+fn test_edit() {
+    let sample = |hz: f32| Sample::new(pitch::Hz(hz), 1.0, ());
+    let zone = |step: (Step, Step), hz: f32| {
+        let mut zone = test_zone(step, (0.0, 1.0));
+        zone.sample = sample(hz);
+        zone
+    };
+    let steps = |map: &Map<()>| -> Vec<(Step, Step)> {
+        map.pairs.iter().map(|zone| (zone.range.step.min, zone.range.step.max)).collect()
+    };
+    let base_hzs = |map: &Map<()>| -> Vec<f32> {
+        map.pairs.iter().map(|zone| zone.sample.base_hz.hz()).collect()
+    };
+
+    // Transposing shifts every range while leaving the samples and key switches unchanged.
+    let mut map = Map::from_pairs(vec![zone((0, 59), 1.0), zone((60, 127), 2.0)]);
+    map.insert_fractional(Range { min: 59.75, max: 60.25 }, Range { min: 0.0, max: 1.0 }, sample(3.0));
+    map.key_switches = Some(Range { min: 0, max: 1 });
+    map.transpose(12);
+    assert_eq!(steps(&map), vec![(12, 71), (72, 72), (72, 139)]);
+    assert_eq!(map.pairs[1].step_bounds(), (71.75, 72.25));
+    assert_eq!(base_hzs(&map), vec![1.0, 3.0, 2.0]);
+    assert_eq!(map.key_switches, Some(Range { min: 0, max: 1 }));
+    assert_eq!(map.zone_idx(11, 1.0), None);
+    assert_eq!(map.zone_idx(72, 1.0), Some(1));
+    assert_eq!(map.zone_idx(73, 1.0), Some(2));
+    map.transpose(-12);
+    assert_eq!(steps(&map), vec![(0, 59), (60, 60), (60, 127)]);
+
+    // Merging orders the zones by the given priority, giving the merged zones new ids.
+    let existing = || Map::from_pairs(vec![zone((0, 127), 1.0), zone((100, 127), 2.0)]);
+    let merged = || {
+        let mut map = Map::from_pairs(vec![zone((0, 127), 3.0), zone((0, 99), 4.0)]);
+        map.key_switches = Some(Range { min: 0, max: 1 });
+        map
+    };
+    for &(priority, expected) in &[(Priority::Existing, [1.0, 2.0, 3.0, 4.0]),
+                                   (Priority::Merged, [3.0, 4.0, 1.0, 2.0]),
+                                   (Priority::Range, [4.0, 1.0, 3.0, 2.0])] {
+        let mut map = existing();
+        let ids: Vec<ZoneId> = map.iter().map(|(id, _)| id).collect();
+        map.merge(merged(), priority);
+        assert_eq!(base_hzs(&map), expected.to_vec(), "{:?}", priority);
+        assert_eq!(map.zone(ids[0]).unwrap().sample.base_hz.hz(), 1.0);
+        assert_eq!(map.zone(ids[1]).unwrap().sample.base_hz.hz(), 2.0);
+        let mut all_ids: Vec<ZoneId> = map.iter().map(|(id, _)| id).collect();
+        all_ids.sort();
+        all_ids.dedup();
+        assert_eq!(all_ids.len(), 4);
+        let first = map.zone_idx(64, 1.0).map(|idx| map.pairs[idx].sample.base_hz.hz());
+        assert_eq!(first, Some(expected[0]));
+        // The key switches of the merged map are used as the existing map has none.
+        assert_eq!(map.key_switches, Some(Range { min: 0, max: 1 }));
+    }
+    let mut map = existing();
+    map.key_switches = Some(Range { min: 120, max: 127 });
+    map.merge(merged(), Priority::Existing);
+    assert_eq!(map.key_switches, Some(Range { min: 120, max: 127 }));
+
+    // Restricting clips every range to the window, removing zones outside of it.
+    let mut map = Map::from_pairs(vec![zone((0, 127), 1.0), zone((100, 127), 2.0)]);
+    map.insert_zone(SampleOverRange::fractional(Range { min: 50.0, max: 70.0 },
+                                                Range { min: 0.0, max: 1.0 },
+                                                sample(3.0)));
+    map.restrict(StepVelRange {
+        step: Range { min: 55, max: 65 },
+        vel: Range { min: 0.25, max: 0.75 },
+    });
+    assert_eq!(steps(&map), vec![(55, 65), (55, 65)]);
+    assert_eq!(base_hzs(&map), vec![1.0, 3.0]);
+    assert!(map.pairs.iter().all(|zone| zone.range.vel == Range { min: 0.25, max: 0.75 }));
+    assert_eq!(map.pairs[1].step_bounds(), (54.5, 65.5));
+    assert_eq!(map.zone_idx(54, 0.5), None);
+    assert_eq!(map.zone_idx(60, 0.5), Some(0));
+    assert_eq!(map.zone_idx(60, 0.8), None);
+
+    // Retaining removes every zone rejected by the predicate.
+    let mut map = Map::from_pairs(vec![zone((0, 127), 1.0), zone((0, 59), 2.0), zone((60, 127), 3.0)]);
+    let ids: Vec<ZoneId> = map.iter().map(|(id, _)| id).collect();
+    map.retain(|zone| zone.sample.base_hz.hz() != 1.0);
+    assert_eq!(base_hzs(&map), vec![2.0, 3.0]);
+    assert_eq!(map.iter().map(|(id, _)| id).collect::<Vec<_>>(), ids[1..].to_vec());
+    assert_eq!(map.zone_idx(64, 1.0), Some(1));
+
+    // Mapping the audio maps every sample of every group, leaving everything else unchanged.
+    let mut map = Map::from_pairs(vec![zone((0, 59), 1.0)]);
+    map.insert_group(test_zone((60, 127), (0.0, 1.0)).range, sample(2.0), vec![sample(3.0)],
+                     Alternation::round_robin());
+    map.key_switches = Some(Range { min: 0, max: 1 });
+    map.set_articulation(1);
+    let ids: Vec<ZoneId> = map.iter().map(|(id, _)| id).collect();
+    let mut count = 0;
+    let mapped = map.clone().map_audio(|()| {
+        count += 1;
+        count
+    });
+    let audio: Vec<Vec<u32>> = mapped.pairs.iter()
+        .map(|zone| Some(&zone.sample).into_iter().chain(&zone.alternates).map(|s| s.audio).collect())
+        .collect();
+    assert_eq!(audio, vec![vec![1], vec![2, 3]]);
+    assert_eq!(mapped.iter().map(|(id, _)| id).collect::<Vec<_>>(), ids);
+    assert_eq!(mapped.map_audio(|_| ()), map);
+}
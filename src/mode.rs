@@ -2,7 +2,8 @@ use audio::Audio;
 use instrument;
 use map::Map;
 use pitch;
-use sampler::PlayingSample;
+use rand;
+use sampler::{self, PlayingSample};
 use std;
 use Velocity;
 
@@ -14,87 +15,119 @@ pub trait Mode {
     /// Handle a `note_on` event.
     ///
     /// Is called immediately following `instrument::Mode::note_on`.
-    fn note_on<A>(&self,
-                  note_hz: pitch::Hz,
-                  note_velocity: Velocity,
-                  map: &Map<A>,
-                  voices: &mut [Option<PlayingSample<A>>])
-        where A: Audio;
+    ///
+    /// The given `rng` is owned by the `Sampler` and is used when choosing between the samples of
+    /// a randomly alternating zone.
+    fn note_on<A, R>(&self,
+                     note_hz: pitch::Hz,
+                     note_velocity: Velocity,
+                     map: &mut Map<A>,
+                     rng: &mut R,
+                     voices: &mut [Option<PlayingSample>])
+        where A: Audio,
+              R: rand::Rng;
 
     /// Handle a `note_off` event.
-    fn note_off<A>(&self,
-                   note_hz: pitch::Hz,
-                   map: &Map<A>,
-                   voices: &mut [Option<PlayingSample<A>>])
-        where A: Audio;
+    fn note_off<A, R>(&self,
+                      note_hz: pitch::Hz,
+                      map: &mut Map<A>,
+                      rng: &mut R,
+                      voices: &mut [Option<PlayingSample>])
+        where A: Audio,
+              R: rand::Rng;
+
+    /// Forget the given note so that it is never fallen back to upon the `note_off` of a later
+    /// note.
+    ///
+    /// Is called by the `Sampler` upon the `note_off` of a one-shot note in place of
+    /// `instrument::Mode::note_off`, and for one-shot notes whose key was released while their
+    /// audio plays on immediately following `instrument::Mode::note_on`.
+    ///
+    /// Does nothing by default, which suits modes that keep no notes to fall back to.
+    fn forget_note(&mut self, _note_hz: pitch::Hz) {}
 }
 
 
 // Helper function for constructing a `PlayingSample`.
-fn play_sample<A>(hz: pitch::Hz, vel: Velocity, map: &Map<A>) -> Option<PlayingSample<A>>
+fn play_sample<A, R>(hz: pitch::Hz,
+                     vel: Velocity,
+                     map: &mut Map<A>,
+                     rng: &mut R) -> Option<PlayingSample>
     where A: Audio,
+          R: rand::Rng,
 {
-    play_sample_from_playhead_idx(0, hz, vel, map)
+    play_sample_from_playhead_idx(0, hz, vel, map, rng)
 }
 
 // Helper function for constructing a `PlayingSample` with a given playhead index.
-fn play_sample_from_playhead_idx<A>(idx: usize,
-                                    hz: pitch::Hz,
-                                    vel: Velocity,
-                                    map: &Map<A>) -> Option<PlayingSample<A>>
+fn play_sample_from_playhead_idx<A, R>(idx: usize,
+                                       hz: pitch::Hz,
+                                       vel: Velocity,
+                                       map: &mut Map<A>,
+                                       rng: &mut R) -> Option<PlayingSample>
     where A: Audio,
+          R: rand::Rng,
 {
-    map.sample(hz, vel).map(|sample| PlayingSample::from_playhead_idx(idx, hz, vel, sample))
+    let layers = map.layer_indices(hz, vel, rng);
+    if layers.is_empty() {
+        return None;
+    }
+    Some(PlayingSample::from_layers(idx, hz, vel, map, &layers))
 }
 
 
 impl Mode for Mono {
 
-    fn note_on<A>(&self,
-                  note_hz: pitch::Hz,
-                  note_vel: Velocity,
-                  map: &Map<A>,
-                  voices: &mut [Option<PlayingSample<A>>])
+    fn note_on<A, R>(&self,
+                     note_hz: pitch::Hz,
+                     note_vel: Velocity,
+                     map: &mut Map<A>,
+                     rng: &mut R,
+                     voices: &mut [Option<PlayingSample>])
         where A: Audio,
+              R: rand::Rng,
     {
         let Mono(ref kind, ref note_stack) = *self;
 
         // If we're in `Legato` mode, begin the note from the same index as the previous note's
         // current state if there is one.
-        let sample = if let instrument::mode::MonoKind::Legato = *kind {
+        let idx = if let instrument::mode::MonoKind::Legato = *kind {
             note_stack.last()
                 .and_then(|&last_hz| {
                     voices.iter()
                         .filter_map(|v| v.as_ref())
                         .find(|sample| instrument::mode::does_hz_match(sample.note_on_hz.hz(), last_hz))
-                        .and_then(|sample| {
-                            let idx = sample.rate_converter.source().idx;
-                            play_sample_from_playhead_idx(idx, note_hz, note_vel, map)
-                        })
+                        .map(|sample| sample.playhead_idx())
                 })
-                .or_else(|| play_sample(note_hz, note_vel, map))
+                .unwrap_or(0)
         // Otherwise, we're in `Retrigger` mode, so start from the beginning of the sample.
         } else {
-            play_sample(note_hz, note_vel, map)
+            0
         };
+        let sample = play_sample_from_playhead_idx(idx, note_hz, note_vel, map, rng);
 
+        // The new note replaces the note of every voice, so there is never a note left to choke.
         if let Some(sample) = sample {
             for voice in voices {
-                *voice = Some(sample.clone());
+                *voice = Some(sample);
             }
         }
     }
 
-    fn note_off<A>(&self,
-                   note_hz: pitch::Hz,
-                   map: &Map<A>,
-                   voices: &mut [Option<PlayingSample<A>>])
+    fn note_off<A, R>(&self,
+                      note_hz: pitch::Hz,
+                      map: &mut Map<A>,
+                      rng: &mut R,
+                      voices: &mut [Option<PlayingSample>])
         where A: Audio,
+              R: rand::Rng,
     {
         let Mono(kind, ref note_stack) = *self;
 
+        // One-shot samples ignore `note_off` and play through to the end of their audio.
         let should_reset = voices.iter()
             .filter_map(|v| v.as_ref())
+            .filter(|v| !v.is_one_shot())
             .any(|v| instrument::mode::does_hz_match(v.note_on_hz.hz(), note_hz.hz()));
 
         if !should_reset {
@@ -104,16 +137,26 @@ impl Mode for Mono {
         // If there is some note to fall back to, do so.
         if let Some(&fallback_note_hz) = note_stack.last() {
             let hz = fallback_note_hz.into();
+
+            // Look up the fallback layers once so that all voices play the same samples of each
+            // group.
+            let vel = match voices.iter().filter_map(|v| v.as_ref()).next() {
+                Some(playing_sample) => playing_sample.note_on_vel,
+                None => return,
+            };
+            let layers = map.layer_indices(hz, vel, rng);
+            if layers.is_empty() {
+                return;
+            }
+
             for voice in voices {
                 if let Some(ref mut playing_sample) = *voice {
                     let idx = match kind {
                         MonoKind::Retrigger => 0,
-                        MonoKind::Legato => playing_sample.rate_converter.source().idx,
+                        MonoKind::Legato => playing_sample.playhead_idx(),
                     };
                     let vel = playing_sample.note_on_vel;
-                    if let Some(sample) = play_sample_from_playhead_idx(idx, hz, vel, map) {
-                        *playing_sample = sample;
-                    }
+                    *playing_sample = PlayingSample::from_layers(idx, hz, vel, map, &layers);
                 }
             }
         }
@@ -122,22 +165,34 @@ impl Mode for Mono {
         // `instrument` run out.
     }
 
+    fn forget_note(&mut self, note_hz: pitch::Hz) {
+        let Mono(_, ref mut note_stack) = *self;
+        note_stack.retain(|&hz| !instrument::mode::does_hz_match(hz, note_hz.hz()));
+    }
+
 }
 
 impl Mode for Poly {
 
-    fn note_on<A>(&self,
-                  note_hz: pitch::Hz,
-                  note_vel: Velocity,
-                  map: &Map<A>,
-                  voices: &mut [Option<PlayingSample<A>>])
+    fn note_on<A, R>(&self,
+                     note_hz: pitch::Hz,
+                     note_vel: Velocity,
+                     map: &mut Map<A>,
+                     rng: &mut R,
+                     voices: &mut [Option<PlayingSample>])
         where A: Audio,
+              R: rand::Rng,
     {
-        let sample = match play_sample(note_hz, note_vel, map) {
+        let sample = match play_sample(note_hz, note_vel, map, rng) {
             Some(sample) => sample,
             None => return,
         };
 
+        // Fade out the notes cut off by the new note.
+        if let Some(group) = sample.choke_group {
+            sampler::choke(voices, group, map.choke_fade);
+        }
+
         // Find the right voice to play the note.
         let mut oldest = None;
         let mut oldest_time_of_note_on = std::time::Instant::now();
@@ -157,42 +212,56 @@ impl Mode for Poly {
         }
     }
 
-    fn note_off<A>(&self,
-                   _note_hz: pitch::Hz,
-                   _map: &Map<A>,
-                   _voices: &mut [Option<PlayingSample<A>>])
+    fn note_off<A, R>(&self,
+                      _note_hz: pitch::Hz,
+                      _map: &mut Map<A>,
+                      _rng: &mut R,
+                      _voices: &mut [Option<PlayingSample>])
         where A: Audio,
+              R: rand::Rng,
     {
         // No need to do anything here as voices will be set to `None` when frames yielded by
-        // `instrument` run out.
+        // `instrument` run out. Voices playing one-shot samples are never released by the
+        // `instrument` and are instead freed by the `Sampler` once their audio runs out.
     }
 
 }
 
 impl Mode for Dynamic {
 
-    fn note_on<A>(&self,
-                  note_hz: pitch::Hz,
-                  note_vel: Velocity,
-                  map: &Map<A>,
-                  voices: &mut [Option<PlayingSample<A>>])
+    fn note_on<A, R>(&self,
+                     note_hz: pitch::Hz,
+                     note_vel: Velocity,
+                     map: &mut Map<A>,
+                     rng: &mut R,
+                     voices: &mut [Option<PlayingSample>])
         where A: Audio,
+              R: rand::Rng,
     {
         match *self {
-            Dynamic::Mono(ref mono) => mono.note_on(note_hz, note_vel, map, voices),
-            Dynamic::Poly(ref poly) => poly.note_on(note_hz, note_vel, map, voices),
+            Dynamic::Mono(ref mono) => mono.note_on(note_hz, note_vel, map, rng, voices),
+            Dynamic::Poly(ref poly) => poly.note_on(note_hz, note_vel, map, rng, voices),
         }
     }
 
-    fn note_off<A>(&self,
-                   note_hz: pitch::Hz,
-                   map: &Map<A>,
-                   voices: &mut [Option<PlayingSample<A>>])
+    fn note_off<A, R>(&self,
+                      note_hz: pitch::Hz,
+                      map: &mut Map<A>,
+                      rng: &mut R,
+                      voices: &mut [Option<PlayingSample>])
         where A: Audio,
+              R: rand::Rng,
     {
         match *self {
-            Dynamic::Mono(ref mono) => mono.note_off(note_hz, map, voices),
-            Dynamic::Poly(ref poly) => poly.note_off(note_hz, map, voices),
+            Dynamic::Mono(ref mono) => mono.note_off(note_hz, map, rng, voices),
+            Dynamic::Poly(ref poly) => poly.note_off(note_hz, map, rng, voices),
+        }
+    }
+
+    fn forget_note(&mut self, note_hz: pitch::Hz) {
+        match *self {
+            Dynamic::Mono(ref mut mono) => mono.forget_note(note_hz),
+            Dynamic::Poly(ref mut poly) => poly.forget_note(note_hz),
         }
     }
 
@@ -0,0 +1,273 @@
+//! Filename conventions for describing where a sample belongs within a `Map`.
+//!
+//! A `Pattern` is written as the literal text of a filename (without its extension) with
+//! `{field}` placeholders standing in for the parts that vary between files. The supported
+//! fields are:
+//!
+//! - `{root}` - the root note of the sample, either as a note name (`C4`, `F#3`, `Db2`) or as a
+//! MIDI note number (`060`). See the `note` module for the accepted note names.
+//! - `{low}` and `{high}` - the lowest and highest steps of the sample's key range, written in
+//! the same manner as `{root}`.
+//! - `{layer}` - the index of the sample's velocity layer. Layers split the velocity range evenly.
+//! - `{vel}` - the highest MIDI velocity (`0` to `127`) of the sample's velocity layer.
+//! - `{rr}` - the index of the sample within its round-robin group.
+//! - `{*}` - any text, which is ignored.
+//!
+//! For example, `Piano_C4_v3_rr2` matches `{*}_{root}_v{layer}_rr{rr}` and `kick_036_127`
+//! matches `{*}_{root}_{vel}`.
+//!
+//! Literal text is matched case-insensitively.
+
+use Step;
+use note::{self, MiddleC};
+use std;
+
+
+/// A filename convention used to read `Fields` from the names of sample files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+    middle_c: MiddleC,
+}
+
+/// The values read from a filename by a `Pattern`.
+///
+/// Fields that do not appear within the `Pattern` are always `None`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fields {
+    /// The root note of the sample.
+    pub root: Option<Step>,
+    /// The lowest step of the sample's key range.
+    pub low: Option<Step>,
+    /// The highest step of the sample's key range.
+    pub high: Option<Step>,
+    /// The index of the sample's velocity layer.
+    pub layer: Option<u32>,
+    /// The highest MIDI velocity of the sample's velocity layer.
+    pub vel: Option<u8>,
+    /// The index of the sample within its round-robin group.
+    pub rr: Option<u32>,
+}
+
+/// Errors that may occur while parsing a `Pattern`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A `{` was opened without a matching `}`.
+    Unclosed,
+    /// The placeholder with the given name is not a known field.
+    UnknownField(String),
+}
+
+/// A single part of a `Pattern`.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    Field(Field),
+}
+
+/// The fields that may be named by a placeholder.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Field {
+    Root,
+    Low,
+    High,
+    Layer,
+    Vel,
+    RoundRobin,
+    Any,
+}
+
+
+impl Pattern {
+
+    /// Parse a `Pattern` from the given string.
+    pub fn new(pattern: &str) -> Result<Self, Error> {
+        let mut tokens = Vec::new();
+        let mut rest = pattern;
+        while !rest.is_empty() {
+            match rest.find('{') {
+                Some(0) => {
+                    let end = try!(rest.find('}').ok_or(Error::Unclosed));
+                    let field = match &rest[1..end] {
+                        "root" => Field::Root,
+                        "low" => Field::Low,
+                        "high" => Field::High,
+                        "layer" => Field::Layer,
+                        "vel" => Field::Vel,
+                        "rr" => Field::RoundRobin,
+                        "*" => Field::Any,
+                        name => return Err(Error::UnknownField(name.to_owned())),
+                    };
+                    tokens.push(Token::Field(field));
+                    rest = &rest[end + 1..];
+                },
+                Some(start) => {
+                    tokens.push(Token::Literal(rest[..start].to_owned()));
+                    rest = &rest[start..];
+                },
+                None => {
+                    tokens.push(Token::Literal(rest.to_owned()));
+                    rest = "";
+                },
+            }
+        }
+        Ok(Pattern { tokens: tokens, middle_c: MiddleC::default() })
+    }
+
+    /// The octave convention used to read note names. By default, middle C is named `C4`.
+    pub fn middle_c(self, middle_c: MiddleC) -> Self {
+        Pattern { middle_c: middle_c, ..self }
+    }
+
+    /// Attempt to read the `Fields` from the given filename.
+    ///
+    /// The filename should not include its extension.
+    ///
+    /// Returns `None` if the filename does not match the `Pattern`.
+    pub fn parse(&self, name: &str) -> Option<Fields> {
+        let mut fields = Fields::default();
+        if match_tokens(&self.tokens, name, self.middle_c, &mut fields) {
+            Some(fields)
+        } else {
+            None
+        }
+    }
+
+    /// Attempt to read the `Fields` from the stem of the file at the given path.
+    pub fn parse_path(&self, path: &std::path::Path) -> Option<Fields> {
+        path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| self.parse(stem))
+    }
+
+}
+
+
+/// Match the given `tokens` against the whole of `s`, writing each matched field to `fields`.
+///
+/// Fields match the shortest text that parses successfully and allows the remaining tokens to
+/// match, backtracking to longer text where necessary.
+fn match_tokens(tokens: &[Token], s: &str, middle_c: MiddleC, fields: &mut Fields) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return s.is_empty(),
+    };
+    match *token {
+        Token::Literal(ref literal) => {
+            let len = literal.len();
+            s.len() >= len && s.is_char_boundary(len)
+                && s[..len].eq_ignore_ascii_case(literal)
+                && match_tokens(rest, &s[len..], middle_c, fields)
+        },
+        Token::Field(field) => {
+            let ends = s.char_indices().map(|(i, _)| i).skip(1).chain(Some(s.len()));
+            for end in ends {
+                let before = fields.clone();
+                if read_field(field, &s[..end], middle_c, fields)
+                    && match_tokens(rest, &s[end..], middle_c, fields) {
+                    return true;
+                }
+                *fields = before;
+            }
+            false
+        },
+    }
+}
+
+/// Read the given `field` from the text `s`, returning `false` if it is not valid for the field.
+fn read_field(field: Field, s: &str, middle_c: MiddleC, fields: &mut Fields) -> bool {
+    match field {
+        Field::Root => note::parse(s, middle_c).map(|step| fields.root = Some(step)).is_some(),
+        Field::Low => note::parse(s, middle_c).map(|step| fields.low = Some(step)).is_some(),
+        Field::High => note::parse(s, middle_c).map(|step| fields.high = Some(step)).is_some(),
+        Field::Layer => read_number(s).map(|layer| fields.layer = Some(layer)).is_some(),
+        Field::Vel => match read_number(s) {
+            Some(vel) if vel <= 127 => { fields.vel = Some(vel as u8); true },
+            _ => false,
+        },
+        Field::RoundRobin => read_number(s).map(|rr| fields.rr = Some(rr)).is_some(),
+        Field::Any => true,
+    }
+}
+
+/// Read an unsigned decimal number consisting only of ASCII digits.
+fn read_number(s: &str) -> Option<u32> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+
+impl std::error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Unclosed => "unclosed placeholder",
+            Error::UnknownField(_) => "unknown field",
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+
+#[test]
+fn test_new() {
+    assert!(Pattern::new("{*}_{root}_v{layer}_rr{rr}").is_ok());
+    assert!(Pattern::new("").is_ok());
+    assert_eq!(Pattern::new("{root"), Err(Error::Unclosed));
+    assert_eq!(Pattern::new("{*}_{root}_{"), Err(Error::Unclosed));
+    assert_eq!(Pattern::new("{note}"), Err(Error::UnknownField("note".to_owned())));
+    assert_eq!(Pattern::new("{Root}"), Err(Error::UnknownField("Root".to_owned())));
+    assert_eq!(Pattern::new("{}"), Err(Error::UnknownField("".to_owned())));
+}
+
+#[test]
+fn test_parse() {
+    let parse = |pattern: &str, name: &str| Pattern::new(pattern).unwrap().parse(name);
+
+    assert_eq!(parse("{*}_{root}_v{layer}_rr{rr}", "Piano_C4_v3_rr2"),
+               Some(Fields { root: Some(60), layer: Some(3), rr: Some(2), ..Fields::default() }));
+    assert_eq!(parse("{*}_{root}_{vel}", "kick_036_127"),
+               Some(Fields { root: Some(36), vel: Some(127), ..Fields::default() }));
+    assert_eq!(parse("{low}-{high}", "C3-G3"),
+               Some(Fields { low: Some(48), high: Some(55), ..Fields::default() }));
+    assert_eq!(parse("{root}", "F#3"), Some(Fields { root: Some(54), ..Fields::default() }));
+    assert_eq!(parse("{root}", "Db-1"), Some(Fields { root: Some(1), ..Fields::default() }));
+
+    // Velocities beyond 127 do not match.
+    assert_eq!(parse("{*}_{vel}", "snare_128"), None);
+    assert_eq!(parse("{*}_{vel}", "snare_100"),
+               Some(Fields { vel: Some(100), ..Fields::default() }));
+
+    // Fields backtrack to longer text when the rest of the pattern fails to match.
+    assert_eq!(parse("{*}_{rr}", "Grand_Piano_Soft_4"),
+               Some(Fields { rr: Some(4), ..Fields::default() }));
+    assert_eq!(parse("{root}{vel}", "C4100"),
+               Some(Fields { root: Some(60), vel: Some(100), ..Fields::default() }));
+    assert_eq!(parse("{layer}{rr}", "123"),
+               Some(Fields { layer: Some(1), rr: Some(23), ..Fields::default() }));
+
+    // Literals are matched case-insensitively.
+    assert_eq!(parse("PIANO_{root}", "piano_c4"),
+               Some(Fields { root: Some(60), ..Fields::default() }));
+    assert_eq!(parse("{*}_RR{rr}", "Piano_C4_rr2"),
+               Some(Fields { rr: Some(2), ..Fields::default() }));
+
+    // The whole name must match.
+    assert_eq!(parse("{*}_{root}", "Piano_C4_v3"), None);
+    assert_eq!(parse("Piano_{root}", "Grand_Piano_C4"), None);
+    assert_eq!(parse("{*}_{root}_v{layer}", "Piano_C4_vx"), None);
+    assert_eq!(parse("", ""), Some(Fields::default()));
+    assert_eq!(parse("", "Piano"), None);
+}
+
+#[test]
+fn test_middle_c() {
+    let pattern = Pattern::new("{*}_{root}").unwrap().middle_c(MiddleC::C3);
+    assert_eq!(pattern.parse("Piano_C3"), Some(Fields { root: Some(60), ..Fields::default() }));
+    assert_eq!(pattern.parse_path(std::path::Path::new("samples/Piano_C3.wav")),
+               Some(Fields { root: Some(60), ..Fields::default() }));
+}
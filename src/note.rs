@@ -0,0 +1,201 @@
+//! Parsing of musical note names and MIDI note numbers, as commonly found in sample file names.
+//!
+//! Note names consist of a letter from `A` to `G`, an optional accidental and an octave, i.e.
+//! `C4`, `F#3`, `Db-1` or `A♭2`. Accepted accidentals are `#`, `♯` and `sh` for sharps and `b` and
+//! `♭` for flats. Letters and accidentals are case-insensitive.
+//!
+//! Notes beyond `MIN_STEP` and `MAX_STEP`, i.e. `C12` or `Cb-1`, are never parsed or found.
+
+use {Step, MIN_STEP, MAX_STEP};
+use pitch;
+
+
+/// The octave number given to middle C (MIDI note number `60`).
+///
+/// Manufacturers disagree on this, so sample libraries are named using either convention.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MiddleC {
+    /// Middle C is named `C3`, as used by Yamaha and many DAWs.
+    C3,
+    /// Middle C is named `C4`, as in scientific pitch notation and `pitch::LetterOctave`.
+    C4,
+}
+
+
+impl MiddleC {
+
+    /// The octave number of middle C under this convention.
+    pub fn octave(&self) -> pitch::Octave {
+        match *self {
+            MiddleC::C3 => 3,
+            MiddleC::C4 => 4,
+        }
+    }
+
+}
+
+impl Default for MiddleC {
+    fn default() -> Self {
+        MiddleC::C4
+    }
+}
+
+
+/// Parse the entirety of `s` as either a note name or a MIDI note number from `0` to `127`.
+pub fn parse(s: &str, middle_c: MiddleC) -> Option<Step> {
+    parse_midi(s).or_else(|| parse_name(s, middle_c))
+}
+
+/// Parse the entirety of `s` as a MIDI note number from `0` to `127`.
+pub fn parse_midi(s: &str) -> Option<Step> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match s.parse::<u32>() {
+        Ok(n) if n <= 127 => Some(n as Step),
+        _ => None,
+    }
+}
+
+/// Parse the entirety of `s` as a note name such as `C4`, `F#3` or `Db-1`.
+pub fn parse_name(s: &str, middle_c: MiddleC) -> Option<Step> {
+    match read_name(s, middle_c) {
+        Some((step, len)) if len == s.len() => Some(step),
+        _ => None,
+    }
+}
+
+/// Search `s` for a note.
+///
+/// Notes must not be directly preceded or followed by a letter or digit, so `c1` is not found
+/// within `c12` or `ac10`. Note names are preferred over MIDI note numbers. Where there are
+/// several candidates of the same kind, the first is returned.
+pub fn find(s: &str, middle_c: MiddleC) -> Option<Step> {
+    let is_boundary = |c: Option<char>| c.map_or(true, |c| !c.is_alphanumeric());
+
+    let starts = || s.char_indices()
+        .filter(move |&(i, _)| is_boundary(s[..i].chars().next_back()))
+        .map(|(i, _)| i);
+
+    let name = starts().filter_map(|i| {
+        read_name(&s[i..], middle_c)
+            .and_then(|(step, len)| {
+                if is_boundary(s[i + len..].chars().next()) { Some(step) } else { None }
+            })
+    }).next();
+
+    name.or_else(|| starts().filter_map(|i| {
+        let len = s[i..].find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len() - i);
+        if is_boundary(s[i + len..].chars().next()) { parse_midi(&s[i..i + len]) } else { None }
+    }).next())
+}
+
+
+/// Read a note name from the start of `s`, returning its step along with the number of bytes
+/// that it spans.
+///
+/// Returns `None` if the step lies beyond `MIN_STEP` or `MAX_STEP`.
+fn read_name(s: &str, middle_c: MiddleC) -> Option<(Step, usize)> {
+    let letter = match s.chars().next().map(|c| c.to_ascii_lowercase()) {
+        Some('c') => 0,
+        Some('d') => 2,
+        Some('e') => 4,
+        Some('f') => 5,
+        Some('g') => 7,
+        Some('a') => 9,
+        Some('b') => 11,
+        _ => return None,
+    };
+    let mut len = 1;
+
+    const ACCIDENTALS: &'static [(&'static str, i32)] = &[
+        ("#", 1), ("♯", 1), ("sh", 1), ("b", -1), ("♭", -1),
+    ];
+    let rest = &s[len..];
+    let accidental = ACCIDENTALS.iter().find(|&&(name, _)| {
+        rest.len() >= name.len() && rest.is_char_boundary(name.len())
+            && rest[..name.len()].eq_ignore_ascii_case(name)
+    });
+    let accidental = match accidental {
+        Some(&(name, semitones)) => {
+            len += name.len();
+            semitones
+        },
+        None => 0,
+    };
+
+    let sign = match s[len..].chars().next() {
+        Some('-') => {
+            len += 1;
+            -1
+        },
+        _ => 1,
+    };
+    let digits = s[len..].find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len() - len);
+    // Limit the octave to two digits so that it can never overflow a `Step`.
+    if digits == 0 || digits > 2 {
+        return None;
+    }
+    let octave = match s[len..len + digits].parse::<pitch::Octave>() {
+        Ok(octave) => sign * octave,
+        Err(_) => return None,
+    };
+    len += digits;
+
+    // `pitch::LetterOctave` names middle C `C4`, so shift octaves named relative to `C3`.
+    let octave = octave + 4 - middle_c.octave();
+    let c = pitch::LetterOctave(pitch::Letter::C, octave).step() as i32;
+    let step = (c + letter + accidental) as Step;
+    if step < MIN_STEP || step > MAX_STEP {
+        return None;
+    }
+    Some((step, len))
+}
+
+
+#[test]
+fn test_parse() {
+    use self::MiddleC::{C3, C4};
+
+    assert_eq!(parse("C4", C4), Some(60));
+    assert_eq!(parse("c4", C4), Some(60));
+    assert_eq!(parse("C3", C3), Some(60));
+    assert_eq!(parse("A4", C4), Some(69));
+    assert_eq!(parse("C#4", C4), Some(61));
+    assert_eq!(parse("Csh4", C4), Some(61));
+    assert_eq!(parse("C♯4", C4), Some(61));
+    assert_eq!(parse("Db4", C4), Some(61));
+    assert_eq!(parse("D♭4", C4), Some(61));
+    assert_eq!(parse("Bb-1", C4), Some(10));
+    assert_eq!(parse("C-1", C4), Some(0));
+    assert_eq!(parse("C-2", C3), Some(0));
+    assert_eq!(parse("G9", C4), Some(127));
+    assert_eq!(parse("Cb-1", C4), None);
+    assert_eq!(parse("G#9", C4), None);
+    assert_eq!(parse("G8", C3), Some(127));
+    assert_eq!(parse("Ab8", C3), None);
+    assert_eq!(parse("C12", C4), None);
+    assert_eq!(parse("060", C4), Some(60));
+    assert_eq!(parse("127", C4), Some(127));
+    assert_eq!(parse("128", C4), None);
+    assert_eq!(parse("H4", C4), None);
+    assert_eq!(parse("C", C4), None);
+    assert_eq!(parse("C4x", C4), None);
+    assert_eq!(parse("", C4), None);
+}
+
+#[test]
+fn test_find() {
+    use self::MiddleC::{C3, C4};
+
+    assert_eq!(find("Piano_C4_v3_rr2", C4), Some(60));
+    assert_eq!(find("Piano_C3_v3_rr2", C3), Some(60));
+    assert_eq!(find("kick_036_127", C4), Some(36));
+    assert_eq!(find("Strings Db4 ff", C4), Some(61));
+    assert_eq!(find("Bass-Bb-1", C4), Some(10));
+    assert_eq!(find("c12", C4), None);
+    assert_eq!(find("Lead Cb-1 A2", C4), Some(45));
+    assert_eq!(find("ac10", C4), None);
+    assert_eq!(find("take2 A#2", C4), Some(46));
+    assert_eq!(find("snare", C4), None);
+}
@@ -1,10 +1,12 @@
 use audio::Audio;
-use instrument::{self, Instrument};
+use instrument::{self, Instrument, NoteState};
 use map::{self, Map};
 use pitch;
+use rand::{self, SeedableRng};
 use sample::{self, Frame, Sample as PcmSample};
 use std;
 use time;
+use velocity;
 use Velocity;
 
 /// A Sampler instrument.
@@ -15,7 +17,19 @@ pub struct Sampler<M, NFG, A>
 {
     pub instrument: Instrument<M, NFG>,
     pub map: Map<A>,
-    voices: Voices<A>,
+    /// The curve applied to the velocity of each `note_on` before it is used to select zones.
+    pub selection_curve: velocity::Curve,
+    /// The curve applied to the velocity of each `note_on` before it is used as the note's gain.
+    pub gain_curve: velocity::Curve,
+    /// Determines the amplitude of each sample from the velocity of its note and its `base_vel`.
+    pub gain_model: velocity::GainModel,
+    voices: Voices,
+    /// Used to choose between the samples of randomly alternating zones.
+    ///
+    /// Unseeded by default so that offline renders are deterministic.
+    ///
+    /// The state of the generator is not serialized, so a deserialized `Sampler` begins unseeded.
+    rng: rand::XorShiftRng,
 }
 
 /// Samples that are currently active along with the `Hz` with which they were triggered.
@@ -27,50 +41,99 @@ pub struct Sampler<M, NFG, A>
 /// In `Poly` mode, each pair is mapped directly to each of the `Instrument`'s `voices` via their
 /// `Vec` indices.
 #[derive(Clone)]
-pub struct Voices<A>
-    where A: Audio,
-{
-    map: Vec<Option<PlayingSample<A>>>,
+pub struct Voices {
+    map: Vec<Option<PlayingSample>>,
 }
 
 /// A sample that is currently being played back.
-#[derive(Clone)]
-pub struct PlayingSample<A>
-    where A: Audio,
-{
+///
+/// The audio of each layer is referred to by index into the `Sampler`'s `Map` rather than being
+/// owned, so that starting a note requires no allocation or reference counting.
+#[derive(Copy, Clone)]
+pub struct PlayingSample {
     /// The pitch in hz at which the `note_on` was triggered.
     pub note_on_hz: pitch::Hz,
     pub note_on_vel: Velocity,
-    base_hz: pitch::Hz,
-    base_vel: Velocity,
-    /// Rate-adjustable interpolation of audio.
-    pub rate_converter: sample::rate::Converter<Playhead<A>>,
+    /// Each of the samples that are blended together to play the note.
+    pub layers: [Option<Layer>; map::MAX_LAYERS],
     /// The time at which the `PlayingSample` was constructed.
     pub time_of_note_on: std::time::Instant,
+    /// The `choke_group` of the zones played by the note, if any.
+    pub choke_group: Option<u32>,
+    /// If `Some`, the note has been choked and is fading out.
+    choke: Option<Choke>,
+    /// Whether or not the key of a one-shot note has been released while its audio plays on.
+    key_released: bool,
 }
 
-/// An owned iterator that wraps an audio file but does not 
-#[derive(Clone)]
-pub struct Playhead<A>
-    where A: Audio,
-{
-    /// The position of the playhead over the `Sample`.
+/// The progress of the fade out of a choked note or of a layer making way for a release note.
+#[derive(Copy, Clone, Debug)]
+struct Choke {
+    /// The duration of the fade.
+    fade: time::Ms,
+    /// The time for which the note has been fading.
+    elapsed: time::Ms,
+}
+
+/// A single `Sample` that is played back as one layer of a `PlayingSample`.
+#[derive(Copy, Clone)]
+pub struct Layer {
+    /// The sample within the `Map` whose audio is played.
+    pub sample: map::SampleIdx,
+    base_hz: pitch::Hz,
+    base_vel: Velocity,
+    key_tracking: f32,
+    one_shot: bool,
+    /// The playback rate ratio of the `Sample`'s `tune_cents`.
+    tune: f32,
+    /// The linear amplitude of the `Sample`'s `gain_db`.
+    amp: f32,
+    pan: f32,
+    /// The amount by which the layer's amplitude is scaled when blended with the other layers.
+    pub gain: f32,
+    /// Rate-adjustable, looping playback of the audio.
+    pub playhead: Playhead,
+    /// A start offset by which the playhead is skipped forward once the playback sample rate is
+    /// known, i.e. upon the first frame.
+    pending_offset: Option<time::Ms>,
+    /// If `Some`, the layer is fading out having been released within a voice that went on to
+    /// play a release note.
+    fade: Option<Choke>,
+}
+
+/// Plays back some audio at an adjustable rate, interpolating linearly between frames and
+/// wrapping around a sustain loop with sub-frame accuracy.
+///
+/// The audio itself is passed to each call to `next_frame`.
+#[derive(Copy, Clone)]
+pub struct Playhead {
+    /// The index of the frame at or before the position of the playhead over the `Sample`.
     pub idx: usize,
-    audio: A,
+    /// The position of the playhead between the frame at `idx` and the following frame.
+    frac: f64,
+    /// Whether or not the playhead is moving backwards through a `Backward` or `PingPong` loop.
+    reverse: bool,
+    /// The loop within which the playhead repeats.
+    ///
+    /// Becomes `None` when a `PlayToEnd` loop is released.
+    sustain_loop: Option<map::Loop>,
+    /// The number of frames within the audio.
+    len: usize,
 }
 
 /// An iterator yielding one frame from the `Sampler` at a time.
 pub struct Frames<'a, A: 'a, NF: 'a>
     where A: Audio,
 {
-    voices: &'a mut Voices<A>,
+    voices: &'a mut Voices,
+    map: &'a Map<A>,
+    gain_model: velocity::GainModel,
+    sample_hz: f64,
     instrument_frames: instrument::Frames<'a, NF>,
 }
 
 
-impl<A> std::fmt::Debug for Voices<A>
-    where A: Audio,
-{
+impl std::fmt::Debug for Voices {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         write!(f, "Voices {{ num: {:?} }}", self.map.len())
     }
@@ -118,8 +181,12 @@ impl<M, NFG, A> Sampler<M, NFG, A>
         let n_voices = instrument.voices.len();
         Sampler {
             map: map,
+            selection_curve: velocity::Curve::Linear,
+            gain_curve: velocity::Curve::Linear,
+            gain_model: velocity::GainModel::Legacy,
             voices: Voices { map: vec![None; n_voices] },
             instrument: instrument,
+            rng: rand::XorShiftRng::new_unseeded(),
         }
     }
 
@@ -133,14 +200,22 @@ impl<M, NFG, A> Sampler<M, NFG, A>
     {
         let Sampler {
             map,
+            selection_curve,
+            gain_curve,
+            gain_model,
             voices,
             instrument,
+            rng,
         } = self;
 
         Sampler {
             map: map,
+            selection_curve: selection_curve,
+            gain_curve: gain_curve,
+            gain_model: gain_model,
             voices: voices,
             instrument: f(instrument),
+            rng: rng,
         }
     }
 
@@ -174,6 +249,49 @@ impl<M, NFG, A> Sampler<M, NFG, A>
         self.map_instrument(|inst| inst.release(release))
     }
 
+    /// Build the `Sampler` with the given curve applied to `note_on` velocities for both zone
+    /// selection and gain.
+    pub fn velocity_curve(self, curve: velocity::Curve) -> Self {
+        self.selection_curve(curve.clone()).gain_curve(curve)
+    }
+
+    /// Build the `Sampler` with the given curve applied to `note_on` velocities before they are
+    /// used to select zones.
+    pub fn selection_curve(mut self, curve: velocity::Curve) -> Self {
+        self.selection_curve = curve;
+        self
+    }
+
+    /// Build the `Sampler` with the given curve applied to `note_on` velocities before they are
+    /// used as the gain of the note.
+    pub fn gain_curve(mut self, curve: velocity::Curve) -> Self {
+        self.gain_curve = curve;
+        self
+    }
+
+    /// Build the `Sampler` with the given model by which velocities determine the amplitude of
+    /// each sample.
+    pub fn gain_model(mut self, gain_model: velocity::GainModel) -> Self {
+        self.gain_model = gain_model;
+        self
+    }
+
+    /// Build the `Sampler` with its random number generator seeded with the given `seed`.
+    ///
+    /// **Panics** if the `seed` is all zeros.
+    pub fn seed(mut self, seed: [u32; 4]) -> Self {
+        self.set_seed(seed);
+        self
+    }
+
+    /// Re-seed the random number generator used to choose between the samples of randomly
+    /// alternating zones.
+    ///
+    /// **Panics** if the `seed` is all zeros.
+    pub fn set_seed(&mut self, seed: [u32; 4]) {
+        self.rng.reseed(seed);
+    }
+
     /// Set the number of voices to use for 
     pub fn set_num_voices(&mut self, n: usize) {
         self.instrument.set_num_voices(n);
@@ -181,27 +299,132 @@ impl<M, NFG, A> Sampler<M, NFG, A>
     }
 
     /// Begin playback of a note.
+    ///
+    /// The `selection_curve` and `gain_curve` are applied to the given velocity before it is used
+    /// to select zones and as the gain of the note respectively. The gain is then converted to an
+    /// amplitude by the `gain_model`.
     #[inline]
     pub fn note_on<T>(&mut self, note_hz: T, note_vel: Velocity)
         where M: instrument::Mode + super::Mode,
               T: Into<pitch::Hz>
     {
-        let Sampler { ref mut instrument, ref mut voices, ref map, .. } = *self;
+        let Sampler {
+            ref mut instrument,
+            ref mut voices,
+            ref mut map,
+            ref selection_curve,
+            ref gain_curve,
+            gain_model,
+            ref mut rng,
+        } = *self;
         let hz = note_hz.into();
-        instrument.note_on(hz, note_vel);
-        super::Mode::note_on(&mut instrument.mode, hz, note_vel, map, &mut voices.map);
+        // Key switches change the articulation without sounding anything.
+        if map.key_switch(hz) {
+            return;
+        }
+        let selection_vel = selection_curve.apply(note_vel);
+        let gain_vel = gain_curve.apply(note_vel);
+        instrument.note_on(hz, gain_model.note_amp(gain_vel));
+        // The instrument may have stacked the notes of one-shot voices whose keys were already
+        // released, in which case the new note must not fall back to them upon its `note_off`.
+        for playing in voices.map.iter().filter_map(|p| p.as_ref()) {
+            let released_hz = playing.note_on_hz.hz();
+            if playing.key_released && !instrument::mode::does_hz_match(released_hz, hz.hz()) {
+                super::Mode::forget_note(&mut instrument.mode, playing.note_on_hz);
+            }
+        }
+        super::Mode::note_on(&mut instrument.mode, hz, selection_vel, map, rng, &mut voices.map);
     }
 
     /// Stop playback of the note that was triggered with the matching frequency.
+    ///
+    /// Voices playing one-shot samples are left to play through to the end of their audio.
+    ///
+    /// If the `Map` has `Release` zones for the note, their samples are started using the velocity
+    /// of the released note, on a free voice if there is one or otherwise mixed into the voice of
+    /// the released note.
     #[inline]
     pub fn note_off<T>(&mut self, note_hz: T)
         where M: instrument::Mode + super::Mode,
               T: Into<pitch::Hz>
     {
-        let Sampler { ref mut instrument, ref mut voices, ref map, .. } = *self;
+        let Sampler { ref mut instrument, ref mut voices, ref mut map, ref mut rng, .. } = *self;
         let hz = note_hz.into();
-        super::Mode::note_off(&mut instrument.mode, hz, map, &mut voices.map);
-        instrument.note_off(hz);
+        if map.is_key_switch(hz) {
+            return;
+        }
+
+        // Remember the voice playing the note so that we can tell whether it was released.
+        let released = voices.map.iter().zip(&instrument.voices).enumerate()
+            .filter_map(|(i, (playing, voice))| match *playing {
+                Some(ref p) if !p.is_one_shot()
+                    && instrument::mode::does_hz_match(p.note_on_hz.hz(), hz.hz())
+                    && voice.note.as_ref().map_or(false, |note| note.state == NoteState::Playing) => {
+                    // The instrument's note holds the amplitude given by the `gain_model`.
+                    let gain_vel = voice.note.as_ref().map_or(p.note_on_vel, |note| note.vel);
+                    Some((i, p.note_on_vel, gain_vel, p.time_of_note_on))
+                },
+                _ => None,
+            })
+            .next();
+
+        super::Mode::note_off(&mut instrument.mode, hz, map, rng, &mut voices.map);
+
+        // The instrument must never release the note of a one-shot voice. If one is playing the
+        // note, only the most recent held voice playing the same note is released, if any.
+        let is_playing = |voice: &instrument::Voice<NFG::NoteFreq>| {
+            voice.note.as_ref().map_or(false, |note| {
+                note.state == NoteState::Playing && instrument::mode::does_hz_match(note.hz, hz.hz())
+            })
+        };
+        let is_one_shot = |i: usize| voices.map[i].as_ref().map_or(false, |p| p.is_one_shot());
+        let n_voices = instrument.voices.len();
+        if !(0..n_voices).any(|i| is_one_shot(i) && is_playing(&instrument.voices[i])) {
+            instrument.note_off(hz);
+        } else {
+            let held = (0..n_voices)
+                .filter(|&i| !is_one_shot(i) && is_playing(&instrument.voices[i]))
+                .max_by_key(|&i| instrument.voices[i].playhead);
+            match held {
+                Some(i) => {
+                    let Instrument {
+                        ref mut mode, ref note_freq_gen, detune, voices: ref mut inst_voices, ..
+                    } = *instrument;
+                    let held_voice = &mut inst_voices[i..i + 1];
+                    instrument::Mode::note_off(mode, hz.hz(), detune, note_freq_gen, held_voice);
+                },
+                // A `Mono` note stack may still hold the note.
+                None => super::Mode::forget_note(&mut instrument.mode, hz),
+            }
+        }
+        for playing in voices.map.iter_mut().filter_map(|p| p.as_mut()) {
+            let is_match = instrument::mode::does_hz_match(playing.note_on_hz.hz(), hz.hz());
+            if playing.is_one_shot() && is_match {
+                playing.key_released = true;
+            }
+        }
+
+        // Let the voices released by the instrument leave their sustain loops.
+        for (voice, playing) in instrument.voices.iter().zip(&mut voices.map) {
+            if let Some(ref mut playing) = *playing {
+                if voice.note.as_ref().map_or(false, |note| note.state != NoteState::Playing) {
+                    playing.release();
+                }
+            }
+        }
+
+        if let Some((i, vel, gain_vel, time_of_note_on)) = released {
+            let is_released = instrument.voices[i].note.as_ref()
+                .map_or(true, |note| note.state != NoteState::Playing);
+            if is_released {
+                let held = ms_since(time_of_note_on);
+                let layers = map.release_layer_indices(hz, vel, held, rng);
+                if !layers.is_empty() {
+                    let playing = PlayingSample::from_layers(0, hz, vel, map, &layers).one_shot();
+                    play_release(instrument, &mut voices.map, i, playing, gain_vel, map.choke_fade);
+                }
+            }
+        }
     }
 
     /// Stop playback and clear the current notes.
@@ -211,7 +434,9 @@ impl<M, NFG, A> Sampler<M, NFG, A>
     {
         let Sampler { ref mut instrument, ref mut voices, .. } = *self;
         instrument.stop();
-        voices.map.clear();
+        for voice in &mut voices.map {
+            *voice = None;
+        }
     }
 
     /// Produces an iterator that yields `Frame`s of audio data.
@@ -220,8 +445,38 @@ impl<M, NFG, A> Sampler<M, NFG, A>
               <A::Frame as Frame>::Sample: sample::Duplex<f64>,
               <<A::Frame as Frame>::Sample as PcmSample>::Float: sample::FromSample<f32>,
     {
+        // Free the voices of one-shot samples that have played to the end of their audio and of
+        // choked notes that have faded out.
+        //
+        // Layers follow their zones should the zones have moved within the map since the last
+        // frames, while the layers of removed zones are dropped along with any note left silent.
+        let Sampler { ref mut instrument, ref mut voices, ref map, .. } = *self;
+        for (voice, playing) in instrument.voices.iter_mut().zip(&mut voices.map) {
+            if let Some(ref mut playing) = *playing {
+                for slot in playing.layers.iter_mut() {
+                    // Layers that made way for a release note are dropped once faded out.
+                    let is_faded = slot.and_then(|layer| layer.fade).map_or(false, |f| f.gain() == 0.0);
+                    match slot.as_ref().and_then(|layer| map.locate(layer.sample)) {
+                        Some(idx) if !is_faded => slot.as_mut().unwrap().sample = idx,
+                        _ => *slot = None,
+                    }
+                }
+            }
+            let is_finished = playing.as_ref().map_or(false, |p| {
+                let is_silent = p.layers.iter().all(Option::is_none);
+                (p.is_one_shot() && p.is_finished()) || p.is_choked() || is_silent
+            });
+            if is_finished {
+                voice.stop();
+                *playing = None;
+            }
+        }
+
         Frames {
             voices: &mut self.voices,
+            map: &self.map,
+            gain_model: self.gain_model,
+            sample_hz: sample_hz,
             instrument_frames: self.instrument.frames(sample_hz),
         }
     }
@@ -254,11 +509,98 @@ impl<M, NFG, A> Sampler<M, NFG, A>
 }
 
 
+/// Start the given one-shot release note at the amplitude `gain_vel` of the released note,
+/// choking the notes of its `choke_group`.
+///
+/// The note is played by the first free voice, or otherwise by the oldest voice that is not
+/// playing the released note. If every voice is playing the released note, the release note is
+/// instead mixed into the voice `released_idx`, whose own layers fade out over the instrument's
+/// release as they would have otherwise.
+fn play_release<M, NFG>(instrument: &mut Instrument<M, NFG>,
+                        voices: &mut [Option<PlayingSample>],
+                        released_idx: usize,
+                        mut playing: PlayingSample,
+                        gain_vel: Velocity,
+                        choke_fade: time::Ms)
+    where NFG: instrument::NoteFreqGenerator,
+{
+    // The key of the release note has already been released.
+    playing.key_released = true;
+    let hz = playing.note_on_hz.hz();
+
+    let mut free = None;
+    let mut oldest = None;
+    let mut oldest_time_of_note_on = std::time::Instant::now();
+    for (i, (voice, p)) in instrument.voices.iter().zip(voices.iter()).enumerate() {
+        match (voice.note.as_ref(), p.as_ref()) {
+            (Some(_), Some(p)) => {
+                let is_released_note = instrument::mode::does_hz_match(p.note_on_hz.hz(), hz);
+                if !is_released_note && p.time_of_note_on < oldest_time_of_note_on {
+                    oldest_time_of_note_on = p.time_of_note_on;
+                    oldest = Some(i);
+                }
+            },
+            _ => {
+                free = Some(i);
+                break;
+            },
+        }
+    }
+    let (idx, is_mixed) = match free.or(oldest) {
+        Some(idx) => (idx, false),
+        None => (released_idx, true),
+    };
+
+    // Choke the group of the release note without choking the voice that is to play it.
+    if let Some(group) = playing.choke_group {
+        let target = voices[idx].take();
+        choke(voices, group, choke_fade);
+        voices[idx] = target;
+    }
+
+    let freq = instrument.note_freq_gen.generate(hz, instrument.detune, Some(&instrument.voices[idx]));
+    let voice = &mut instrument.voices[idx];
+    if is_mixed {
+        // Continue the released voice without restarting its attack so that the release note
+        // sounds.
+        voice.note_on(hz, freq, gain_vel);
+        if let Some(ref mut released) = voices[idx] {
+            released.mix_release(&playing, instrument.release_ms);
+        }
+    } else {
+        voice.reset_playhead();
+        voice.note_on(hz, freq, gain_vel);
+        voices[idx] = Some(playing);
+    }
+}
+
+/// Chokes every note within the given `voices` that belongs to the given `choke_group`, fading
+/// each out over the given duration.
+///
+/// Should be called by each `Mode` upon `note_on` with the `choke_group` of the new note. `Mono`
+/// modes have no need to, as each `note_on` replaces the note of every voice regardless of group.
+pub fn choke(voices: &mut [Option<PlayingSample>], choke_group: u32, fade: time::Ms) {
+    for playing in voices.iter_mut().filter_map(|v| v.as_mut()) {
+        if playing.choke_group == Some(choke_group) {
+            playing.choke(fade);
+        }
+    }
+}
+
+/// The time elapsed since the given `instant`.
+fn ms_since(instant: std::time::Instant) -> time::Ms {
+    let elapsed = instant.elapsed();
+    time::Ms(elapsed.as_secs() as f64 * 1_000.0 + elapsed.subsec_nanos() as f64 / 1_000_000.0)
+}
+
+
 #[cfg(feature="serde_serialization")]
 pub mod private {
     use audio::Audio;
     use instrument::{self, Instrument};
     use map::Map;
+    use rand;
+    use velocity;
 
     /// A private constructor for use within serde.rs.
     pub fn new<M, NFG, A>(instrument: Instrument<M, NFG>,
@@ -270,77 +612,402 @@ pub mod private {
         super::Sampler {
             instrument: instrument,
             map: map,
+            selection_curve: velocity::Curve::Linear,
+            gain_curve: velocity::Curve::Linear,
+            gain_model: velocity::GainModel::Legacy,
             voices: super::Voices { map: vec![None; num_voices] },
+            rng: rand::XorShiftRng::new_unseeded(),
         }
     }
 }
 
 
-impl<A> PlayingSample<A>
-    where A: Audio,
-{
+impl PlayingSample {
 
-    /// Construct a new `PlayingSample` from the given note hz, velocity and the associated
-    /// `Sample` from the `Map`.
-    pub fn new(hz: pitch::Hz, vel: Velocity, sample: map::Sample<A>) -> Self {
-        Self::from_playhead_idx(0, hz, vel, sample)
-    }
-
-    /// Construct a new `PlayingSample` from the given note hz, velocity and the associated
-    /// `Sample` from the `Map`.
+    /// Construct a new `PlayingSample` that blends each of the given layers of the `map`, as
+    /// returned by `Map::layer_indices`.
     ///
-    /// The given `Sample`'s audio will begin playing from the given `idx`.
-    pub fn from_playhead_idx(idx: usize,
-                             hz: pitch::Hz,
-                             vel: Velocity,
-                             sample: map::Sample<A>) -> Self
+    /// The audio of each layer will begin playing from the given `idx`. If the `idx` is `0`, each
+    /// layer instead begins from the `start_offset` of its zone for the given velocity.
+    pub fn from_layers<A>(idx: usize,
+                          hz: pitch::Hz,
+                          vel: Velocity,
+                          map: &Map<A>,
+                          layers: &map::Layers) -> Self
+        where A: Audio,
     {
-        let map::Sample { base_hz, base_vel, audio } = sample;
-        let playhead = Playhead::from_idx(idx, audio);
-        let rate_converter = sample::rate::Converter::scale_playback_hz(playhead, 1.0);
-        PlayingSample {
+        let mut playing = PlayingSample {
             note_on_hz: hz,
             note_on_vel: vel,
+            layers: [None; map::MAX_LAYERS],
+            time_of_note_on: std::time::Instant::now(),
+            choke_group: layers.iter()
+                .filter_map(|&(sample_idx, _)| map.pairs.get(sample_idx.zone))
+                .filter_map(|zone| zone.choke_group)
+                .next(),
+            choke: None,
+            key_released: false,
+        };
+        let samples = layers.iter().filter_map(|&(sample_idx, gain)| {
+            let offset = match idx {
+                0 => map.pairs.get(sample_idx.zone).and_then(|zone| zone.start_offset_at(vel)),
+                _ => None,
+            };
+            map.get_sample(sample_idx)
+                .map(|sample| Layer::from_playhead_idx(idx, sample_idx, sample, gain).start_offset(offset))
+        });
+        for (slot, layer) in playing.layers.iter_mut().zip(samples) {
+            *slot = Some(layer);
+        }
+        playing
+    }
+
+    /// The index of the playhead over the first layer's audio.
+    ///
+    /// This is used by `Legato` modes to continue playback of a new note from the same position.
+    pub fn playhead_idx(&self) -> usize {
+        self.layers.iter().filter_map(|layer| layer.as_ref()).next().map_or(0, |layer| layer.playhead.idx)
+    }
+
+    /// Whether or not the note should ignore `note_off`, playing through to the end of its audio.
+    ///
+    /// This is the case if any of its layers are playing a one-shot `Sample`.
+    pub fn is_one_shot(&self) -> bool {
+        self.layers.iter().filter_map(|layer| layer.as_ref()).any(|layer| layer.one_shot)
+    }
+
+    /// Whether or not every layer has played to the end of its audio.
+    pub fn is_finished(&self) -> bool {
+        self.layers.iter().filter_map(|layer| layer.as_ref()).all(|layer| layer.playhead.is_finished())
+    }
+
+    /// Release the sustain loop of each layer that leaves its loop upon `note_off`.
+    pub fn release(&mut self) {
+        for layer in self.layers.iter_mut().filter_map(|layer| layer.as_mut()) {
+            layer.playhead.release();
+        }
+    }
+
+    /// Begin fading out the note over the given duration.
+    ///
+    /// Does nothing if the note is already fading, so that repeatedly choking a note does not
+    /// restart its fade.
+    pub fn choke(&mut self, fade: time::Ms) {
+        if self.choke.is_none() {
+            self.choke = Some(Choke { fade: fade, elapsed: time::Ms(0.0) });
+        }
+    }
+
+    /// Whether or not the note was choked and has finished fading out.
+    pub fn is_choked(&self) -> bool {
+        self.choke.map_or(false, |choke| choke.gain() == 0.0)
+    }
+
+    /// The gain of the note's choke fade at the current frame, stepping the fade forward by a
+    /// single frame at the given sample rate.
+    fn next_choke_gain(&mut self, sample_hz: f64) -> f32 {
+        self.choke.as_mut().map_or(1.0, |choke| choke.next_gain(sample_hz))
+    }
+
+    /// Mixes the layers of the given `release` note into this released note.
+    ///
+    /// The existing layers fade out over the given duration, making room for the layers of the
+    /// `release` note should there be no empty layers left.
+    fn mix_release(&mut self, release: &PlayingSample, fade: time::Ms) {
+        for layer in self.layers.iter_mut().filter_map(|layer| layer.as_mut()) {
+            if layer.fade.is_none() {
+                layer.fade = Some(Choke { fade: fade, elapsed: time::Ms(0.0) });
+            }
+        }
+        for &layer in release.layers.iter().filter_map(|layer| layer.as_ref()) {
+            let slot = self.layers.iter().position(|slot| slot.is_none())
+                .or_else(|| self.layers.iter().position(|slot| slot.and_then(|l| l.fade).is_some()));
+            match slot {
+                Some(slot) => self.layers[slot] = Some(layer),
+                None => break,
+            }
+        }
+        self.time_of_note_on = release.time_of_note_on;
+        self.choke_group = release.choke_group;
+        self.key_released = true;
+    }
+
+    /// Plays every layer as though its `Sample` were one-shot, ignoring `note_off` and its sustain
+    /// loop.
+    fn one_shot(mut self) -> Self {
+        for layer in self.layers.iter_mut().filter_map(|layer| layer.as_mut()) {
+            layer.one_shot = true;
+            layer.playhead.sustain_loop = None;
+        }
+        self
+    }
+
+}
+
+
+impl Choke {
+
+    /// The gain of the fade at its current position, falling linearly from `1.0` to `0.0`.
+    fn gain(&self) -> f32 {
+        let fade = self.fade.ms();
+        if fade <= 0.0 {
+            return 0.0;
+        }
+        (1.0 - self.elapsed.ms() / fade).max(0.0) as f32
+    }
+
+    /// The gain of the fade at its current position, stepping the fade forward by a single frame
+    /// at the given sample rate.
+    fn next_gain(&mut self, sample_hz: f64) -> f32 {
+        let gain = self.gain();
+        self.elapsed = time::Ms(self.elapsed.ms() + 1_000.0 / sample_hz);
+        gain
+    }
+
+}
+
+
+impl Layer {
+
+    /// Construct a new `Layer` that plays the given `Sample` (found at `sample_idx` within the
+    /// `Map`) from the given `idx` at `gain`.
+    pub fn from_playhead_idx<A>(idx: usize,
+                                sample_idx: map::SampleIdx,
+                                sample: &map::Sample<A>,
+                                gain: f32) -> Self
+        where A: Audio,
+    {
+        let map::Sample {
+            base_hz, base_vel, ref audio, key_tracking, one_shot, tune_cents, gain_db, pan, sustain_loop,
+        } = *sample;
+        // One-shot samples are never released, so they would never leave their loop.
+        let sustain_loop = if one_shot { None } else { sustain_loop };
+        let playhead = Playhead::from_idx(idx, Audio::data(audio).len()).sustain_loop(sustain_loop);
+        Layer {
+            sample: sample_idx,
             base_hz: base_hz,
             base_vel: base_vel,
-            rate_converter: rate_converter,
-            time_of_note_on: std::time::Instant::now(),
+            key_tracking: key_tracking,
+            one_shot: one_shot,
+            tune: 2.0f32.powf(tune_cents / 1200.0),
+            amp: 10.0f32.powf(gain_db / 20.0),
+            pan: pan,
+            gain: gain,
+            playhead: playhead,
+            pending_offset: None,
+            fade: None,
+        }
+    }
+
+    /// Skip the playhead forward by the given offset.
+    ///
+    /// Offsets given in `Ms` are applied upon the first frame, once the sample rate is known.
+    pub fn start_offset(mut self, offset: Option<map::StartOffset>) -> Self {
+        match offset {
+            Some(map::StartOffset::Frames(frames)) => self.playhead.skip(frames),
+            Some(map::StartOffset::Ms(ms)) => self.pending_offset = Some(ms),
+            None => (),
         }
+        self
+    }
+
+    /// Applies any start offset that is waiting on the playback sample rate.
+    #[inline]
+    fn apply_pending_offset(&mut self, sample_hz: f64) {
+        if let Some(ms) = self.pending_offset.take() {
+            self.playhead.skip(map::StartOffset::Ms(ms).frames(sample_hz));
+        }
+    }
+
+    /// Yields the next frame of the layer's audio `data`, played back at the rate necessary for
+    /// the given note `hz`.
+    #[inline]
+    pub fn next_frame<F>(&mut self, data: &[F], hz: f32) -> Option<F>
+        where F: Frame,
+              F::Sample: sample::Duplex<f64>,
+    {
+        let playback_hz_scale = self.playback_hz_scale(hz);
+        self.playhead.next_frame(data, playback_hz_scale as f64)
+    }
+
+    /// The amount by which the playback rate of the audio should be scaled to play the note at
+    /// the given `hz`, taking the `key_tracking` of the `Sample` into account.
+    #[inline]
+    pub fn playback_hz_scale(&self, hz: f32) -> f32 {
+        let ratio = hz / self.base_hz.hz();
+        let tracked = if self.key_tracking == 1.0 {
+            ratio
+        } else {
+            ratio.powf(self.key_tracking)
+        };
+        tracked * self.tune
+    }
+
+    /// Applies the `Sample`'s `pan` to the given frame.
+    ///
+    /// Frames that do not have exactly two channels are returned unchanged.
+    #[inline]
+    pub fn pan<F>(&self, frame: F) -> F
+        where F: Frame,
+              <F::Sample as PcmSample>::Float: sample::FromSample<f32>,
+    {
+        if self.pan == 0.0 || F::n_channels() != 2 {
+            return frame;
+        }
+        let left = (1.0 - self.pan).min(1.0);
+        let right = (1.0 + self.pan).min(1.0);
+        F::from_fn(|channel| {
+            let amp = if channel == 0 { left } else { right };
+            frame.channel(channel)
+                .map_or(F::Sample::equilibrium(), |&s| s.mul_amp(amp.to_sample()))
+        })
     }
 
 }
 
 
-impl<A> Playhead<A>
-    where A: Audio,
-{
-    /// Wrap the given `Audio` with a `Playhead` starting from 0.
-    pub fn new(audio: A) -> Self {
-        Self::from_idx(0, audio)
+impl Playhead {
+    /// A `Playhead` over audio of `len` frames starting from 0.
+    pub fn new(len: usize) -> Self {
+        Self::from_idx(0, len)
     }
 
-    /// Wrap the given `Audio` with a `Playhead` starting from the given playhead index.
-    pub fn from_idx(idx: usize, audio: A) -> Self {
+    /// A `Playhead` over audio of `len` frames starting from the given playhead index.
+    pub fn from_idx(idx: usize, len: usize) -> Self {
         Playhead {
             idx: idx,
-            audio: audio,
+            frac: 0.0,
+            reverse: false,
+            sustain_loop: None,
+            len: len,
         }
     }
-}
 
-impl<A> Iterator for Playhead<A>
-    where A: Audio,
-{
-    type Item = A::Frame;
+    /// Build the `Playhead` with the given sustain loop.
+    ///
+    /// Loops that do not lie within the audio are ignored.
+    pub fn sustain_loop(mut self, sustain_loop: Option<map::Loop>) -> Self {
+        let len = self.len;
+        self.sustain_loop = sustain_loop.and_then(|l| if l.is_valid(len) { Some(l) } else { None });
+        self
+    }
+
+    /// Whether or not the playhead has yielded every frame of its audio.
+    pub fn is_finished(&self) -> bool {
+        self.idx >= self.len
+    }
+
+    /// Skip the playhead forward by the given number of frames.
+    ///
+    /// If this skips past the end of the sustain loop, the loop is left so that the playhead plays
+    /// on through to the end of the audio.
+    pub fn skip(&mut self, frames: usize) {
+        self.idx += frames;
+        if self.sustain_loop.map_or(false, |l| self.idx >= l.end) {
+            self.sustain_loop = None;
+            self.reverse = false;
+        }
+    }
+
+    /// Leave the sustain loop if it should only repeat while the note is held.
+    pub fn release(&mut self) {
+        if let Some(map::Loop { release: map::LoopRelease::PlayToEnd, .. }) = self.sustain_loop {
+            self.sustain_loop = None;
+            self.reverse = false;
+        }
+    }
+
+    /// Yields the frame of the given audio `data` at the current position, then steps the
+    /// playhead forward by the given playback rate multiplier.
+    ///
+    /// Returns `None` once the playhead has passed the end of the audio.
     #[inline]
-    fn next(&mut self) -> Option<Self::Item> {
-        let idx = self.idx;
-        self.idx += 1;
-        Audio::data(&self.audio).get(idx).map(|&f| f)
+    pub fn next_frame<F>(&mut self, data: &[F], playback_hz_scale: f64) -> Option<F>
+        where F: Frame,
+              F::Sample: sample::Duplex<f64>,
+    {
+        let frame = {
+            let left = match data.get(self.idx) {
+                Some(&frame) => frame,
+                None => return None,
+            };
+            if self.frac == 0.0 {
+                left
+            } else {
+                let right = data.get(self.next_idx()).map_or(left, |&frame| frame);
+                let frac = self.frac;
+                left.zip_map(right, |l, r| {
+                    let l = l.to_sample::<f64>();
+                    let r = r.to_sample::<f64>();
+                    (l + (r - l) * frac).to_sample()
+                })
+            }
+        };
+        self.advance(playback_hz_scale);
+        Some(frame)
+    }
+
+    /// The index of the frame that follows the frame at `idx`, taking a wrapping loop into
+    /// account.
+    fn next_idx(&self) -> usize {
+        match self.sustain_loop {
+            Some(l) if l.mode != map::LoopMode::PingPong && self.idx + 1 == l.end => l.start,
+            _ => self.idx + 1,
+        }
+    }
+
+    /// Step the position of the playhead by the given playback rate multiplier, wrapping or
+    /// reflecting the position at the bounds of the loop while carrying over any remainder.
+    fn advance(&mut self, playback_hz_scale: f64) {
+        let pos = self.idx as f64 + self.frac;
+        let mut next = if self.reverse { pos - playback_hz_scale } else { pos + playback_hz_scale };
+
+        if let Some(l) = self.sustain_loop {
+            let (start, end) = (l.start as f64, l.end as f64);
+            let last = end - 1.0;
+            match l.mode {
+                map::LoopMode::Forward => if next >= end {
+                    next = start + wrap(next - start, end - start);
+                },
+                map::LoopMode::Backward => {
+                    if !self.reverse && next > last {
+                        next = last - (next - last);
+                        self.reverse = true;
+                    }
+                    if self.reverse && next < start {
+                        next = start + wrap(next - start, end - start);
+                    }
+                },
+                map::LoopMode::PingPong => {
+                    let is_outside = if self.reverse { next < start } else { next > last };
+                    if is_outside {
+                        // Unfold the back-and-forth motion into a single cycle of length `period`
+                        // where the playhead moves forwards for the first half.
+                        let span = last - start;
+                        let period = 2.0 * span;
+                        let phase = if self.reverse { period - (pos - start) } else { pos - start };
+                        let phase = if period > 0.0 { wrap(phase + playback_hz_scale, period) } else { 0.0 };
+                        self.reverse = phase > span;
+                        next = if self.reverse { start + period - phase } else { start + phase };
+                    }
+                },
+            }
+        }
+
+        let next = next.max(0.0);
+        self.idx = next.floor() as usize;
+        self.frac = next - next.floor();
     }
 }
 
 
+/// Wraps `x` to the range `0.0..len`.
+fn wrap(x: f64, len: f64) -> f64 {
+    let x = x % len;
+    if x < 0.0 { x + len } else { x }
+}
+
+
 impl<'a, A, NF> Frames<'a, A, NF>
     where A: Audio,
           <A::Frame as Frame>::Sample: sample::Duplex<f64>,
@@ -352,6 +1019,9 @@ impl<'a, A, NF> Frames<'a, A, NF>
     pub fn next_frame(&mut self) -> A::Frame {
         let Frames {
             ref mut voices,
+            map,
+            gain_model,
+            sample_hz,
             ref mut instrument_frames,
         } = *self;
 
@@ -364,18 +1034,28 @@ impl<'a, A, NF> Frames<'a, A, NF>
                     match *voice {
                         None => return frame,
                         Some(ref mut voice) => {
-                            let playback_hz_scale = hz / voice.base_hz.hz();
-                            voice.rate_converter.set_playback_hz_scale(playback_hz_scale as f64);
-                            match voice.rate_converter.next_frame() {
-                                Some(wave) => {
-                                    let amp = amp * voice.base_vel;
-                                    let scaled = wave.scale_amp(amp.to_sample());
-                                    return frame.zip_map(scaled, |f, s| {
-                                        f.add_amp(s.to_sample::<<<A::Frame as Frame>::Sample as PcmSample>::Signed>())
-                                    });
-                                },
-                                None => return frame,
-                            }
+                            let amp = amp * voice.next_choke_gain(sample_hz);
+                            let layers = voice.layers.iter_mut().filter_map(|layer| layer.as_mut());
+                            return layers.fold(frame, |frame, layer| {
+                                // The layer falls silent if its sample was removed from the map.
+                                let data = match map.get_sample(layer.sample) {
+                                    Some(sample) => Audio::data(&sample.audio),
+                                    None => return frame,
+                                };
+                                layer.apply_pending_offset(sample_hz);
+                                let fade = layer.fade.as_mut().map_or(1.0, |f| f.next_gain(sample_hz));
+                                match layer.next_frame(data, hz) {
+                                    Some(wave) => {
+                                        let base_amp = gain_model.sample_amp(layer.base_vel);
+                                        let amp = amp * base_amp * layer.gain * layer.amp * fade;
+                                        let scaled = layer.pan(wave.scale_amp(amp.to_sample()));
+                                        frame.zip_map(scaled, |f, s| {
+                                            f.add_amp(s.to_sample::<<<A::Frame as Frame>::Sample as PcmSample>::Signed>())
+                                        })
+                                    },
+                                    None => frame,
+                                }
+                            });
                         },
                     }
                 }
@@ -398,3 +1078,554 @@ impl<'a, A, NF> Iterator for Frames<'a, A, NF>
         Some(self.next_frame())
     }
 }
+
+#[cfg(test)]
+fn test_range(step: (::Step, ::Step)) -> map::StepVelRange {
+    map::StepVelRange {
+        step: map::Range { min: step.0, max: step.1 },
+        vel: map::Range { min: 0.0, max: 1.0 },
+    }
+}
+
+#[test]
+fn test_voices_follow_zones() {
+    let sample = || map::Sample::new(pitch::Hz(440.0), 1.0, ());
+    let mut map = Map::empty();
+    let high = map.insert_zone(map::SampleOverRange::new(test_range((60, 127)), sample()));
+    let mut sampler = Sampler::poly((), map).num_voices(2);
+    sampler.note_on(pitch::Step(64.0), 1.0);
+    let zone = |sampler: &Sampler<_, _, ()>, i: usize| {
+        sampler.voices.map[i].as_ref().map(|playing| playing.layers[0].unwrap().sample.zone)
+    };
+    assert_eq!(zone(&sampler, 0), Some(0));
+
+    // The playing note follows its zone as zones are inserted ahead of it.
+    let low = sampler.map.insert_zone(map::SampleOverRange::new(test_range((0, 59)), sample()));
+    sampler.frames(44_100.0);
+    assert_eq!(zone(&sampler, 0), Some(1));
+    assert_eq!(sampler.map.position(high), Some(1));
+
+    // The note falls silent once its zone is removed, while other notes play on.
+    sampler.note_on(pitch::Step(48.0), 1.0);
+    sampler.map.remove(high);
+    sampler.frames(44_100.0);
+    assert_eq!(zone(&sampler, 0), None);
+    assert_eq!(zone(&sampler, 1), Some(0));
+    assert_eq!(sampler.map.position(low), Some(0));
+}
+
+#[test]
+fn test_mono_one_shot_released() {
+    let mut one_shot = map::Sample::new(pitch::Hz(440.0), 1.0, ());
+    one_shot.one_shot = true;
+    let mut map = Map::empty();
+    map.insert(test_range((0, 59)), one_shot);
+    map.insert(test_range((60, 127)), map::Sample::new(pitch::Hz(440.0), 1.0, ()));
+    let (a, b) = (pitch::Step(48.0), pitch::Step(64.0));
+    let mut sampler = Sampler::retrigger((), map);
+
+    // The one-shot note plays on once its key is released.
+    sampler.note_on(a, 1.0);
+    sampler.note_off(a);
+    let note_state = |sampler: &Sampler<_, _, ()>| {
+        sampler.instrument.voices[0].note.as_ref().map(|note| note.state)
+    };
+    assert_eq!(note_state(&sampler), Some(NoteState::Playing));
+
+    // The next note does not fall back to the released one-shot note.
+    sampler.note_on(b, 1.0);
+    assert!(sampler.instrument.mode.1.is_empty());
+    sampler.note_off(b);
+    assert!(sampler.instrument.mode.1.is_empty());
+    let playing = sampler.voices.map[0].unwrap();
+    assert!(instrument::mode::does_hz_match(playing.note_on_hz.hz(), b.to_hz().hz()));
+    assert!(playing.layers[0].unwrap().playhead.idx == 0 && !playing.is_one_shot());
+    match note_state(&sampler) {
+        Some(NoteState::Released(_)) => (),
+        state => panic!("expected the note to be released, found {:?}", state),
+    }
+}
+
+#[test]
+fn test_play_release() {
+    use instrument::mode::Dynamic;
+
+    let sample = || map::Sample::new(pitch::Hz(440.0), 1.0, vec![[0.5; 2]; 100]);
+    let mut map = Map::empty();
+    map.insert(test_range((0, 127)), sample());
+    map.insert_release(test_range((0, 127)), sample(), None);
+    let a = pitch::Step(60.0);
+    let note_state = |sampler: &Sampler<_, _, Vec<[f32; 2]>>, i: usize| {
+        sampler.instrument.voices[i].note.as_ref().map(|note| note.state)
+    };
+    let layers = |sampler: &Sampler<_, _, Vec<[f32; 2]>>, i: usize| -> Vec<Layer> {
+        sampler.voices.map[i].iter().flat_map(|p| p.layers.iter().filter_map(|&l| l)).collect()
+    };
+
+    // With a free voice, the release note leaves the released note to its own release.
+    let mut sampler = Sampler::new(Dynamic::poly(), (), map.clone()).num_voices(2);
+    sampler.note_on(a, 1.0);
+    sampler.note_off(a);
+    match note_state(&sampler, 0) {
+        Some(NoteState::Released(_)) => (),
+        state => panic!("expected the note to be released, found {:?}", state),
+    }
+    assert!(layers(&sampler, 0).iter().all(|layer| !layer.one_shot && layer.fade.is_none()));
+    assert_eq!(note_state(&sampler, 1), Some(NoteState::Playing));
+    assert!(layers(&sampler, 1).iter().all(|layer| layer.one_shot));
+    assert!(sampler.voices.map[1].unwrap().key_released);
+
+    // With a single voice, the release note is mixed into the released note, which fades out.
+    for mode in vec![Dynamic::poly(), Dynamic::retrigger()] {
+        let mut sampler = Sampler::new(mode, (), map.clone());
+        sampler = sampler.release(10.0);
+        sampler.note_on(a, 1.0);
+        sampler.note_off(a);
+        assert_eq!(note_state(&sampler, 0), Some(NoteState::Playing));
+        let mixed = layers(&sampler, 0);
+        assert_eq!(mixed.len(), 2);
+        assert!(mixed.iter().any(|layer| !layer.one_shot && layer.fade.is_some()));
+        assert!(mixed.iter().any(|layer| layer.one_shot && layer.fade.is_none()));
+
+        // The released layer is dropped once it has faded out.
+        {
+            let mut frames = sampler.frames(1_000.0);
+            for _ in 0..20 {
+                frames.next_frame();
+            }
+        }
+        sampler.frames(1_000.0);
+        let remaining = layers(&sampler, 0);
+        assert_eq!(remaining.len(), 1);
+        assert!(remaining[0].one_shot);
+    }
+}
+
+/// Steps the given playhead over a ramp of 8 frames whose values are their indices, yielding the
+/// `idx`, `frac` and frame at each step until the playhead finishes or `n` frames are yielded.
+#[cfg(test)]
+fn test_steps(playhead: &mut Playhead, rate: f64, n: usize) -> Vec<(usize, f64, f32)> {
+    let ramp: Vec<[f32; 1]> = (0..8).map(|i| [i as f32]).collect();
+    let mut steps = vec![];
+    for _ in 0..n {
+        let (idx, frac) = (playhead.idx, playhead.frac);
+        match playhead.next_frame(&ramp, rate) {
+            Some(frame) => steps.push((idx, frac, frame[0])),
+            None => break,
+        }
+    }
+    steps
+}
+
+#[cfg(test)]
+fn test_loop(start: usize,
+             end: usize,
+             mode: map::LoopMode,
+             release: map::LoopRelease) -> Option<map::Loop>
+{
+    Some(map::Loop { start: start, end: end, mode: mode, release: release })
+}
+
+#[test]
+fn test_playhead_forward() {
+    use map::LoopMode::Forward;
+    use map::LoopRelease::Continue;
+
+    // Fractional positions carry over the end of the loop.
+    let mut playhead = Playhead::new(8).sustain_loop(test_loop(2, 6, Forward, Continue));
+    assert_eq!(test_steps(&mut playhead, 1.5, 8),
+               vec![(0, 0.0, 0.0), (1, 0.5, 1.5), (3, 0.0, 3.0), (4, 0.5, 4.5),
+                    (2, 0.0, 2.0), (3, 0.5, 3.5), (5, 0.0, 5.0), (2, 0.5, 2.5)]);
+
+    // Frames at the end of the loop are interpolated towards the start of the loop.
+    let mut playhead = Playhead::from_idx(4, 8).sustain_loop(test_loop(2, 6, Forward, Continue));
+    assert_eq!(test_steps(&mut playhead, 1.5, 5),
+               vec![(4, 0.0, 4.0), (5, 0.5, 3.5), (3, 0.0, 3.0), (4, 0.5, 4.5), (2, 0.0, 2.0)]);
+
+    // Rates greater than the length of the loop wrap more than once.
+    let mut playhead = Playhead::new(8).sustain_loop(test_loop(2, 4, Forward, Continue));
+    assert_eq!(test_steps(&mut playhead, 5.0, 4),
+               vec![(0, 0.0, 0.0), (3, 0.0, 3.0), (2, 0.0, 2.0), (3, 0.0, 3.0)]);
+
+    // A single frame loop repeats its frame.
+    let mut playhead = Playhead::from_idx(3, 8).sustain_loop(test_loop(3, 4, Forward, Continue));
+    assert_eq!(test_steps(&mut playhead, 0.5, 4),
+               vec![(3, 0.0, 3.0), (3, 0.5, 3.0), (3, 0.0, 3.0), (3, 0.5, 3.0)]);
+
+    // Without a loop, the playhead finishes at the end of the audio.
+    let mut playhead = Playhead::from_idx(4, 8);
+    assert_eq!(test_steps(&mut playhead, 1.5, 8),
+               vec![(4, 0.0, 4.0), (5, 0.5, 5.5), (7, 0.0, 7.0)]);
+    assert!(playhead.is_finished());
+}
+
+#[test]
+fn test_playhead_backward() {
+    use map::LoopMode::Backward;
+    use map::LoopRelease::Continue;
+
+    // The playhead plays forwards into the loop, then plays the loop in reverse.
+    let mut playhead = Playhead::new(8).sustain_loop(test_loop(2, 6, Backward, Continue));
+    assert_eq!(test_steps(&mut playhead, 1.5, 10),
+               vec![(0, 0.0, 0.0), (1, 0.5, 1.5), (3, 0.0, 3.0), (4, 0.5, 4.5), (4, 0.0, 4.0),
+                    (2, 0.5, 2.5), (5, 0.0, 5.0), (3, 0.5, 3.5), (2, 0.0, 2.0), (4, 0.5, 4.5)]);
+    assert!(playhead.reverse);
+
+    // A single frame loop repeats its frame.
+    let mut playhead = Playhead::from_idx(2, 8).sustain_loop(test_loop(3, 4, Backward, Continue));
+    assert_eq!(test_steps(&mut playhead, 1.0, 4),
+               vec![(2, 0.0, 2.0), (3, 0.0, 3.0), (3, 0.0, 3.0), (3, 0.0, 3.0)]);
+}
+
+#[test]
+fn test_playhead_ping_pong() {
+    use map::LoopMode::PingPong;
+    use map::LoopRelease::Continue;
+
+    // The playhead is reflected at both ends of the loop, carrying over the remainder.
+    let mut playhead = Playhead::new(8).sustain_loop(test_loop(2, 6, PingPong, Continue));
+    assert_eq!(test_steps(&mut playhead, 1.5, 9),
+               vec![(0, 0.0, 0.0), (1, 0.5, 1.5), (3, 0.0, 3.0), (4, 0.5, 4.5), (4, 0.0, 4.0),
+                    (2, 0.5, 2.5), (3, 0.0, 3.0), (4, 0.5, 4.5), (4, 0.0, 4.0)]);
+
+    // Rates greater than the length of the loop are reflected more than once.
+    let mut playhead = Playhead::new(8).sustain_loop(test_loop(2, 4, PingPong, Continue));
+    assert_eq!(test_steps(&mut playhead, 5.0, 4),
+               vec![(0, 0.0, 0.0), (3, 0.0, 3.0), (2, 0.0, 2.0), (3, 0.0, 3.0)]);
+
+    // A single frame loop repeats its frame.
+    let mut playhead = Playhead::from_idx(2, 8).sustain_loop(test_loop(3, 4, PingPong, Continue));
+    assert_eq!(test_steps(&mut playhead, 1.0, 4),
+               vec![(2, 0.0, 2.0), (3, 0.0, 3.0), (3, 0.0, 3.0), (3, 0.0, 3.0)]);
+}
+
+#[test]
+fn test_playhead_release() {
+    use map::LoopMode::{Backward, Forward};
+    use map::LoopRelease::{Continue, PlayToEnd};
+
+    // Upon release, the playhead leaves the loop and plays through to the end of the audio.
+    let mut playhead = Playhead::new(8).sustain_loop(test_loop(2, 6, Forward, PlayToEnd));
+    assert_eq!(test_steps(&mut playhead, 1.5, 5),
+               vec![(0, 0.0, 0.0), (1, 0.5, 1.5), (3, 0.0, 3.0), (4, 0.5, 4.5), (2, 0.0, 2.0)]);
+    playhead.release();
+    assert_eq!(test_steps(&mut playhead, 1.5, 8),
+               vec![(3, 0.5, 3.5), (5, 0.0, 5.0), (6, 0.5, 6.5)]);
+    assert!(playhead.is_finished());
+
+    // A loop playing in reverse plays forwards once released.
+    let mut playhead = Playhead::new(8).sustain_loop(test_loop(2, 6, Backward, PlayToEnd));
+    test_steps(&mut playhead, 1.5, 5);
+    assert!(playhead.reverse);
+    playhead.release();
+    assert_eq!(test_steps(&mut playhead, 1.5, 8),
+               vec![(2, 0.5, 2.5), (4, 0.0, 4.0), (5, 0.5, 5.5), (7, 0.0, 7.0)]);
+    assert!(playhead.is_finished());
+
+    // A `Continue` loop keeps looping once released.
+    let mut playhead = Playhead::from_idx(4, 8).sustain_loop(test_loop(2, 6, Forward, Continue));
+    playhead.release();
+    assert_eq!(test_steps(&mut playhead, 1.5, 5),
+               vec![(4, 0.0, 4.0), (5, 0.5, 3.5), (3, 0.0, 3.0), (4, 0.5, 4.5), (2, 0.0, 2.0)]);
+}
+
+#[test]
+fn test_clone_round_robin() {
+    let sample = || map::Sample::new(pitch::Hz(440.0), 1.0, ());
+    let mut map = Map::empty();
+    map.insert_group(test_range((0, 127)), sample(), vec![sample(), sample()],
+                     map::Alternation::round_robin());
+    let mut sampler = Sampler::poly((), map).num_voices(1);
+    let next_sample = |sampler: &mut Sampler<_, _, ()>| {
+        sampler.note_on(pitch::Step(60.0), 1.0);
+        sampler.voices.map[0].unwrap().layers[0].unwrap().sample.sample
+    };
+    assert_eq!(next_sample(&mut sampler), 0);
+
+    // A clone continues the round-robin from where the original left off.
+    let mut clone = sampler.clone();
+    assert_eq!(next_sample(&mut clone), 1);
+    assert_eq!(next_sample(&mut clone), 2);
+    assert_eq!(next_sample(&mut sampler), 1);
+
+    // Resetting the alternation starts each zone from its first sample again.
+    sampler.map.reset_alternation();
+    assert_eq!(next_sample(&mut sampler), 0);
+    assert_eq!(next_sample(&mut clone), 0);
+}
+
+#[test]
+fn test_seed() {
+    let sample = |value: f32| map::Sample::new(pitch::Hz(440.0), 1.0, vec![[value; 2]; 4]);
+    let mut map = Map::empty();
+    map.insert_group(test_range((0, 127)), sample(0.1), vec![sample(0.2), sample(0.3), sample(0.4)],
+                     map::Alternation::random());
+    let render = |seed: [u32; 4]| {
+        let mut sampler = Sampler::poly((), map.clone()).num_voices(1).seed(seed);
+        let mut output = vec![];
+        for _ in 0..32 {
+            sampler.note_on(pitch::Hz(440.0), 1.0);
+            let mut frames = [[0.0f32; 2]; 2];
+            sampler.fill_slice(&mut frames, 44_100.0);
+            output.extend(frames.iter().cloned());
+        }
+        output
+    };
+
+    // Renders with the same seed pick the same samples.
+    let output = render([1, 2, 3, 4]);
+    assert_eq!(output, render([1, 2, 3, 4]));
+    assert!(output != render([4, 3, 2, 1]));
+}
+
+#[cfg(test)]
+fn test_layer(sample: &map::Sample<()>) -> Layer {
+    let idx = map::SampleIdx { zone: 0, id: None, sample: 0 };
+    Layer::from_playhead_idx(0, idx, sample, 1.0)
+}
+
+#[test]
+fn test_key_tracking() {
+    let mut sample = map::Sample::new(pitch::Hz(220.0), 1.0, ());
+    for &(key_tracking, scale) in &[(0.0, 1.0), (0.5, std::f32::consts::SQRT_2), (1.0, 2.0), (2.0, 4.0)] {
+        sample.key_tracking = key_tracking;
+        let layer = test_layer(&sample);
+        // An octave above the `base_hz`.
+        let actual = layer.playback_hz_scale(440.0);
+        assert!((actual - scale).abs() < 1e-5, "key tracking {}: {}", key_tracking, actual);
+        // The `base_hz` itself is never repitched.
+        assert!((layer.playback_hz_scale(220.0) - 1.0).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_poly_one_shot_released() {
+    use instrument::mode::Dynamic;
+
+    fn check<M>(mut sampler: Sampler<M, (), Vec<[f32; 2]>>)
+        where M: instrument::Mode + super::Mode,
+    {
+        let a = pitch::Step(60.0);
+        let note_state = |sampler: &Sampler<M, (), Vec<[f32; 2]>>, i: usize| {
+            sampler.instrument.voices[i].note.as_ref().map(|note| note.state)
+        };
+        let render = |sampler: &mut Sampler<M, (), Vec<[f32; 2]>>| {
+            let mut frames = [[0.0; 2]; 10];
+            sampler.fill_slice(&mut frames, 44_100.0);
+        };
+
+        // The one-shot note plays on once its key is released.
+        sampler.note_on(a, 0.25);
+        render(&mut sampler);
+        sampler.note_off(a);
+        assert_eq!(note_state(&sampler, 0), Some(NoteState::Playing));
+        assert!(sampler.voices.map[0].unwrap().key_released);
+
+        // A held note of the same pitch is released in its place, even though the one-shot note
+        // has played for longer.
+        render(&mut sampler);
+        sampler.note_on(a, 0.75);
+        assert!(!sampler.voices.map[1].unwrap().is_one_shot());
+        sampler.note_off(a);
+        assert_eq!(note_state(&sampler, 0), Some(NoteState::Playing));
+        match note_state(&sampler, 1) {
+            Some(NoteState::Released(_)) => (),
+            state => panic!("expected the note to be released, found {:?}", state),
+        }
+    }
+
+    let sample = || map::Sample::new(pitch::Hz(440.0), 1.0, vec![[0.5; 2]; 100]);
+    let mut one_shot = sample();
+    one_shot.one_shot = true;
+    let mut map = Map::empty();
+    map.insert(map::StepVelRange { vel: map::Range { min: 0.0, max: 0.5 }, ..test_range((0, 127)) },
+               one_shot);
+    map.insert(map::StepVelRange { vel: map::Range { min: 0.5, max: 1.0 }, ..test_range((0, 127)) },
+               sample());
+    check(Sampler::poly((), map.clone()).num_voices(2));
+    check(Sampler::new(Dynamic::poly(), (), map.clone()).num_voices(2));
+
+    // A one-shot note of a `Dynamic` mono mode is not fallen back to once released.
+    let mut sampler = Sampler::new(Dynamic::retrigger(), (), map);
+    sampler.note_on(pitch::Step(60.0), 0.25);
+    sampler.note_off(pitch::Step(60.0));
+    assert_eq!(sampler.instrument.voices[0].note.as_ref().map(|note| note.state),
+               Some(NoteState::Playing));
+    sampler.note_on(pitch::Step(64.0), 0.75);
+    sampler.note_off(pitch::Step(64.0));
+    match sampler.instrument.voices[0].note.as_ref().map(|note| note.state) {
+        Some(NoteState::Released(_)) => (),
+        state => panic!("expected the note to be released, found {:?}", state),
+    }
+}
+
+#[test]
+fn test_tune_gain_pan() {
+    let mut sample = map::Sample::new(pitch::Hz(440.0), 1.0, ());
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+
+    // Cents offset the playback rate, with 1200 cents to the octave.
+    for &(cents, rate) in &[(0.0, 1.0), (1200.0, 2.0), (-1200.0, 0.5),
+                            (100.0, 1.0594631), (-50.0, 0.9715319)] {
+        sample.tune_cents = cents;
+        let layer = test_layer(&sample);
+        assert!(close(layer.tune, rate), "{} cents: {}", cents, layer.tune);
+        assert!(close(layer.playback_hz_scale(440.0), rate));
+        assert!(close(layer.playback_hz_scale(880.0), 2.0 * rate));
+    }
+
+    // Decibels are converted to linear amplitude.
+    for &(db, amp) in &[(0.0, 1.0), (-6.0, 0.5011872), (6.0, 1.9952623), (-20.0, 0.1), (20.0, 10.0)] {
+        sample.gain_db = db;
+        let layer = test_layer(&sample);
+        assert!(close(layer.amp, amp), "{} dB: {}", db, layer.amp);
+    }
+
+    // Panning attenuates the opposite channel linearly, leaving the nearer channel unchanged.
+    for &(pan, left, right) in &[(0.0, 0.5, 0.5), (-1.0, 0.5, 0.0), (1.0, 0.0, 0.5),
+                                 (-0.5, 0.5, 0.25), (0.5, 0.25, 0.5)] {
+        sample.pan = pan;
+        let layer = test_layer(&sample);
+        assert_eq!(layer.pan([0.5f32, 0.5]), [left, right], "pan {}", pan);
+        // Frames without exactly two channels pass through unchanged.
+        assert_eq!(layer.pan([0.5f32]), [0.5]);
+        assert_eq!(layer.pan([0.5f32; 3]), [0.5; 3]);
+    }
+}
+
+#[test]
+fn test_key_switch() {
+    let sample = || map::Sample::new(pitch::Hz(440.0), 1.0, ());
+    let mut map = Map::empty();
+    map.key_switches = Some(map::Range { min: 24, max: 26 });
+    map.insert_articulation(0, test_range((60, 127)), sample());
+    map.insert_articulation(1, test_range((60, 127)), sample());
+    map.insert(test_range((0, 59)), sample());
+    let (shared, legato, staccato) = (map.zone_id(0), map.zone_id(1), map.zone_id(2));
+    let mut sampler = Sampler::poly((), map).num_voices(2);
+    let zone = |sampler: &Sampler<_, _, ()>| {
+        sampler.voices.map[0].and_then(|playing| playing.layers[0].unwrap().sample.id)
+    };
+    assert_eq!(sampler.map.articulation(), 0);
+
+    // Key switches sound nothing, but change the zones played by the following notes.
+    sampler.note_on(pitch::Step(25.0), 1.0);
+    assert!(!sampler.is_active());
+    assert!(sampler.instrument.voices.iter().all(|voice| voice.note.is_none()));
+    assert_eq!(sampler.map.articulation(), 1);
+    sampler.note_off(pitch::Step(25.0));
+    assert!(!sampler.is_active());
+    sampler.note_on(pitch::Step(64.0), 1.0);
+    assert_eq!(zone(&sampler), staccato);
+    sampler.stop();
+
+    sampler.note_on(pitch::Step(24.0), 1.0);
+    sampler.note_off(pitch::Step(24.0));
+    assert_eq!(sampler.map.articulation(), 0);
+    sampler.note_on(pitch::Step(64.0), 1.0);
+    assert_eq!(zone(&sampler), legato);
+    sampler.stop();
+
+    // An articulation without zones of its own only plays the zones shared by all articulations.
+    sampler.note_on(pitch::Step(26.0), 1.0);
+    assert_eq!(sampler.map.articulation(), 2);
+    sampler.note_on(pitch::Step(64.0), 1.0);
+    assert!(!sampler.is_active());
+    sampler.note_on(pitch::Step(48.0), 1.0);
+    assert_eq!(zone(&sampler), shared);
+}
+
+#[test]
+fn test_choke() {
+    let sample = || map::Sample::new(pitch::Hz(440.0), 1.0, vec![[0.5; 2]; 1_000]);
+    let mut map = Map::empty();
+    map.insert_choked(1, test_range((60, 61)), sample());
+    map.insert_choked(1, test_range((62, 63)), sample());
+    map.insert_choked(2, test_range((64, 65)), sample());
+    map.choke_fade = time::Ms(10.0);
+    let (closed, open, other) = (pitch::Step(60.0), pitch::Step(62.0), pitch::Step(64.0));
+    let mut sampler = Sampler::poly((), map).num_voices(3);
+    let choke_gain = |sampler: &Sampler<_, _, Vec<[f32; 2]>>, i: usize| {
+        sampler.voices.map[i].and_then(|playing| playing.choke).map(|choke| choke.gain())
+    };
+    let render = |sampler: &mut Sampler<_, _, Vec<[f32; 2]>>, n: usize| {
+        let mut frames = vec![[0.0; 2]; n];
+        sampler.fill_slice(&mut frames, 1_000.0);
+    };
+
+    // A note within the group fades out the notes of the same group, leaving other groups alone.
+    sampler.note_on(open, 1.0);
+    sampler.note_on(other, 1.0);
+    render(&mut sampler, 5);
+    sampler.note_on(closed, 1.0);
+    assert_eq!(choke_gain(&sampler, 0), Some(1.0));
+    assert_eq!(choke_gain(&sampler, 1), None);
+    assert_eq!(choke_gain(&sampler, 2), None);
+
+    // The choked note fades out over the `choke_fade`, after which its voice is freed.
+    render(&mut sampler, 4);
+    assert_eq!(choke_gain(&sampler, 0), Some(0.6));
+    assert!(sampler.voices.map[0].unwrap().layers[0].unwrap().playhead.idx > 0);
+    render(&mut sampler, 6);
+    assert_eq!(choke_gain(&sampler, 0), Some(0.0));
+    assert!(sampler.voices.map[0].unwrap().is_choked());
+    sampler.frames(1_000.0);
+    assert!(sampler.voices.map[0].is_none());
+    assert!(sampler.instrument.voices[0].note.is_none());
+    assert!(sampler.voices.map[1].is_some() && sampler.voices.map[2].is_some());
+
+    // Retriggering a note chokes the note that it retriggers, playing the new note on a free voice.
+    sampler.note_on(closed, 1.0);
+    assert!(sampler.voices.map[0].unwrap().choke.is_none());
+    assert_eq!(choke_gain(&sampler, 1), None);
+    assert_eq!(choke_gain(&sampler, 2), Some(1.0));
+
+    // A `Mono` note replaces the note of the same group outright rather than choking it.
+    let mut sampler = Sampler::retrigger((), sampler.map.clone());
+    sampler.note_on(open, 1.0);
+    sampler.note_on(closed, 1.0);
+    let playing = sampler.voices.map[0].unwrap();
+    assert!(instrument::mode::does_hz_match(playing.note_on_hz.hz(), closed.to_hz().hz()));
+    assert!(playing.choke.is_none());
+}
+
+#[test]
+fn test_start_offset() {
+    let ramp: Vec<[f32; 2]> = (0..100).map(|i| [i as f32; 2]).collect();
+    let hz = pitch::Hz(440.0);
+    let first_frames = |offset: map::StartOffset, sustain_loop: Option<map::Loop>, idx: usize, vel| {
+        let mut sample = map::Sample::new(hz, 1.0, ramp.clone());
+        sample.sustain_loop = sustain_loop;
+        let mut zone = map::SampleOverRange::new(test_range((0, 127)), sample);
+        zone.start_offset = Some(offset);
+        let mut map = Map::from_pairs(vec![zone]);
+        let layers = map.layer_indices(hz, vel, &mut rand::thread_rng());
+        let mut layer = PlayingSample::from_layers(idx, hz, vel, &map, &layers).layers[0].unwrap();
+        layer.apply_pending_offset(1_000.0);
+        (0..2).map(|_| layer.next_frame(&ramp, hz.hz()).map(|frame| frame[0])).collect::<Vec<_>>()
+    };
+
+    // Soft notes begin the full offset into the audio and hard notes at its start.
+    for &offset in &[map::StartOffset::Frames(40), map::StartOffset::Ms(time::Ms(40.0))] {
+        assert_eq!(first_frames(offset, None, 0, 0.0), vec![Some(40.0), Some(41.0)]);
+        assert_eq!(first_frames(offset, None, 0, 0.5), vec![Some(20.0), Some(21.0)]);
+        assert_eq!(first_frames(offset, None, 0, 1.0), vec![Some(0.0), Some(1.0)]);
+        // Legato notes continue from the given index regardless of the offset.
+        assert_eq!(first_frames(offset, None, 5, 0.0), vec![Some(5.0), Some(6.0)]);
+    }
+
+    // An offset beyond the sustain loop leaves the loop, playing on to the end of the audio.
+    let sustain_loop = Some(map::Loop::forward(10, 30));
+    let frames = first_frames(map::StartOffset::Frames(40), sustain_loop, 0, 0.0);
+    assert_eq!(frames, vec![Some(40.0), Some(41.0)]);
+    let mut playhead = Playhead::new(100).sustain_loop(sustain_loop);
+    playhead.skip(29);
+    assert_eq!(playhead.sustain_loop, sustain_loop);
+    playhead.skip(1);
+    assert_eq!(playhead.sustain_loop, None);
+
+    // An offset beyond the end of the audio plays nothing.
+    for &offset in &[map::StartOffset::Frames(100), map::StartOffset::Ms(time::Ms(250.0))] {
+        assert_eq!(first_frames(offset, None, 0, 0.0), vec![None, None]);
+    }
+}
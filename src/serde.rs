@@ -144,13 +144,6 @@ mod audio_range {
     fn test() {
         extern crate serde_json;
 
-        use audio::Audio;
-
-        impl Audio for () {
-            type Frame = [f32; 2];
-            fn data(&self) -> &[Self::Frame] { &[] }
-        }
-
         let range = Range { start: 0, end: 0, audio: () };
         let serialized = serde_json::to_string(&range).unwrap();
 
@@ -343,12 +336,36 @@ mod sample {
                             self.field_idx += 1;
                             Ok(Some(try!(serializer.serialize_struct_elt("audio", &self.t.audio))))
                         },
+                        3 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("key_tracking", &self.t.key_tracking))))
+                        },
+                        4 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("one_shot", &self.t.one_shot))))
+                        },
+                        5 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("tune_cents", &self.t.tune_cents))))
+                        },
+                        6 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("gain_db", &self.t.gain_db))))
+                        },
+                        7 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("pan", &self.t.pan))))
+                        },
+                        8 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("sustain_loop", &self.t.sustain_loop))))
+                        },
                         _ => Ok(None),
                     }
                 }
 
                 fn len(&self) -> Option<usize> {
-                    Some(3)
+                    Some(9)
                 }
             }
 
@@ -377,8 +394,24 @@ mod sample {
                     let mut base_hz = None;
                     let mut base_vel = None;
                     let mut audio = None;
-
-                    enum Field { BaseHz, BaseVel, Audio }
+                    let mut key_tracking = None;
+                    let mut one_shot = None;
+                    let mut tune_cents = None;
+                    let mut gain_db = None;
+                    let mut pan = None;
+                    let mut sustain_loop = None;
+
+                    enum Field {
+                        BaseHz,
+                        BaseVel,
+                        Audio,
+                        KeyTracking,
+                        OneShot,
+                        TuneCents,
+                        GainDb,
+                        Pan,
+                        SustainLoop,
+                    }
 
                     impl serde::Deserialize for Field {
                         fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
@@ -396,7 +429,15 @@ mod sample {
                                         "base_hz" => Ok(Field::BaseHz),
                                         "base_vel" => Ok(Field::BaseVel),
                                         "audio" => Ok(Field::Audio),
-                                        _ => Err(serde::de::Error::custom("expected base_hz, base_vel or audio")),
+                                        "key_tracking" => Ok(Field::KeyTracking),
+                                        "one_shot" => Ok(Field::OneShot),
+                                        "tune_cents" => Ok(Field::TuneCents),
+                                        "gain_db" => Ok(Field::GainDb),
+                                        "pan" => Ok(Field::Pan),
+                                        "sustain_loop" => Ok(Field::SustainLoop),
+                                        _ => Err(serde::de::Error::custom("expected base_hz, base_vel, audio, \
+                                                                           key_tracking, one_shot, tune_cents, \
+                                                                           gain_db, pan or sustain_loop")),
                                     }
                                 }
                             }
@@ -410,6 +451,12 @@ mod sample {
                             Some(Field::BaseHz) => { base_hz = Some(try!(visitor.visit_value())); },
                             Some(Field::BaseVel) => { base_vel = Some(try!(visitor.visit_value())); },
                             Some(Field::Audio) => { audio = Some(try!(visitor.visit_value())); },
+                            Some(Field::KeyTracking) => { key_tracking = Some(try!(visitor.visit_value())); },
+                            Some(Field::OneShot) => { one_shot = Some(try!(visitor.visit_value())); },
+                            Some(Field::TuneCents) => { tune_cents = Some(try!(visitor.visit_value())); },
+                            Some(Field::GainDb) => { gain_db = Some(try!(visitor.visit_value())); },
+                            Some(Field::Pan) => { pan = Some(try!(visitor.visit_value())); },
+                            Some(Field::SustainLoop) => { sustain_loop = Some(try!(visitor.visit_value())); },
                             None => { break; }
                         }
                     }
@@ -429,17 +476,37 @@ mod sample {
                         None => return Err(serde::de::Error::missing_field("audio")),
                     };
 
+                    // Samples without key tracking follow the pitch of the note exactly.
+                    let key_tracking = key_tracking.unwrap_or(1.0);
+                    let one_shot = one_shot.unwrap_or(false);
+
+                    // Samples without trims play back exactly as they were recorded.
+                    let tune_cents = tune_cents.unwrap_or(0.0);
+                    let gain_db = gain_db.unwrap_or(0.0);
+                    let pan = pan.unwrap_or(0.0);
+
+                    // Samples without a loop play through their audio once.
+                    let sustain_loop = sustain_loop.unwrap_or(None);
+
                     try!(visitor.end());
 
                     Ok(Sample {
                         base_hz: base_hz,
                         base_vel: base_vel,
                         audio: audio,
+                        key_tracking: key_tracking,
+                        one_shot: one_shot,
+                        tune_cents: tune_cents,
+                        gain_db: gain_db,
+                        pan: pan,
+                        sustain_loop: sustain_loop,
                     })
                 }
             }
 
-            static FIELDS: &'static [&'static str] = &["base_hz", "base_vel", "audio"];
+            static FIELDS: &'static [&'static str] = &["base_hz", "base_vel", "audio", "key_tracking",
+                                                       "one_shot", "tune_cents", "gain_db", "pan",
+                                                       "sustain_loop"];
 
             let visitor = Visitor { t: std::marker::PhantomData };
 
@@ -451,11 +518,11 @@ mod sample {
     fn test() {
         extern crate serde_json;
 
-        let sample = Sample { base_hz: 440.0.into(), base_vel: 1.0, audio: () };
+        let sample = Sample::new(440.0.into(), 1.0, ());
         let serialized = serde_json::to_string(&sample).unwrap();
 
         println!("{}", serialized);
-        assert_eq!("{\"base_hz\":440,\"base_vel\":1,\"audio\":null}", serialized);
+        assert_eq!("{\"base_hz\":440,\"base_vel\":1,\"audio\":null,\"key_tracking\":1,\"one_shot\":false,\"tune_cents\":0,\"gain_db\":0,\"pan\":0,\"sustain_loop\":null}", serialized);
         
         let deserialized: Sample<()> = serde_json::from_str(&serialized).unwrap();
 
@@ -465,9 +532,65 @@ mod sample {
 
 }
 
+mod zone_id {
+    use map::{self, ZoneId};
+    use super::serde;
+
+    impl serde::Serialize for ZoneId {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            serializer.serialize_newtype_struct("ZoneId", map::private::zone_id_value(*self))
+        }
+    }
+
+    impl serde::Deserialize for ZoneId {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            struct Visitor;
+
+            impl serde::de::Visitor for Visitor {
+                type Value = ZoneId;
+
+                fn visit_u64<E>(&mut self, v: u64) -> Result<Self::Value, E>
+                    where E: serde::de::Error,
+                {
+                    Ok(map::private::zone_id(v))
+                }
+
+                fn visit_newtype_struct<D>(&mut self, deserializer: &mut D) -> Result<Self::Value, D::Error>
+                    where D: serde::Deserializer,
+                {
+                    Ok(map::private::zone_id(try!(super::serde::de::Deserialize::deserialize(deserializer))))
+                }
+            }
+
+            deserializer.deserialize_newtype_struct("ZoneId", Visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+
+        let id = map::private::zone_id(3);
+        let serialized = serde_json::to_string(&id).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("3", &serialized);
+
+        let deserialized: ZoneId = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(id, deserialized);
+    }
+}
+
+
 mod sample_over_range {
     use super::serde;
-    use map::SampleOverRange;
+    use map::{Alternation, SampleOverRange, Trigger};
     use std;
 
     impl<A> serde::Serialize for SampleOverRange<A>
@@ -496,12 +619,60 @@ mod sample_over_range {
                             self.field_idx += 1;
                             Ok(Some(try!(serializer.serialize_struct_elt("sample", &self.t.sample))))
                         },
+                        2 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("alternates", &self.t.alternates))))
+                        },
+                        3 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("alternation", &self.t.alternation))))
+                        },
+                        4 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("vel_crossfade", &self.t.vel_crossfade))))
+                        },
+                        5 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("step_crossfade", &self.t.step_crossfade))))
+                        },
+                        6 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("fractional_step", &self.t.fractional_step))))
+                        },
+                        7 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("trigger", &self.t.trigger))))
+                        },
+                        8 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("release_half_life", &self.t.release_half_life))))
+                        },
+                        9 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("articulation", &self.t.articulation))))
+                        },
+                        10 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("controllers", &self.t.controllers))))
+                        },
+                        11 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("choke_group", &self.t.choke_group))))
+                        },
+                        12 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("start_offset", &self.t.start_offset))))
+                        },
+                        13 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("id", &self.t.id))))
+                        },
                         _ => Ok(None),
                     }
                 }
 
                 fn len(&self) -> Option<usize> {
-                    Some(2)
+                    Some(14)
                 }
             }
 
@@ -529,8 +700,35 @@ mod sample_over_range {
                 {
                     let mut range = None;
                     let mut sample = None;
-
-                    enum Field { Range, Sample }
+                    let mut alternates = None;
+                    let mut alternation = None;
+                    let mut vel_crossfade = None;
+                    let mut step_crossfade = None;
+                    let mut fractional_step = None;
+                    let mut trigger = None;
+                    let mut release_half_life = None;
+                    let mut articulation = None;
+                    let mut controllers = None;
+                    let mut choke_group = None;
+                    let mut start_offset = None;
+                    let mut id = None;
+
+                    enum Field {
+                        Range,
+                        Sample,
+                        Alternates,
+                        Alternation,
+                        VelCrossfade,
+                        StepCrossfade,
+                        FractionalStep,
+                        Trigger,
+                        ReleaseHalfLife,
+                        Articulation,
+                        Controllers,
+                        ChokeGroup,
+                        StartOffset,
+                        Id,
+                    }
 
                     impl serde::Deserialize for Field {
                         fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
@@ -547,7 +745,24 @@ mod sample_over_range {
                                     match value {
                                         "range" => Ok(Field::Range),
                                         "sample" => Ok(Field::Sample),
-                                        _ => Err(serde::de::Error::custom("expected range or sample")),
+                                        "alternates" => Ok(Field::Alternates),
+                                        "alternation" => Ok(Field::Alternation),
+                                        "vel_crossfade" => Ok(Field::VelCrossfade),
+                                        "step_crossfade" => Ok(Field::StepCrossfade),
+                                        "fractional_step" => Ok(Field::FractionalStep),
+                                        "trigger" => Ok(Field::Trigger),
+                                        "release_half_life" => Ok(Field::ReleaseHalfLife),
+                                        "articulation" => Ok(Field::Articulation),
+                                        "controllers" => Ok(Field::Controllers),
+                                        "choke_group" => Ok(Field::ChokeGroup),
+                                        "start_offset" => Ok(Field::StartOffset),
+                                        "id" => Ok(Field::Id),
+                                        _ => Err(serde::de::Error::custom("expected range, sample, alternates, \
+                                                                           alternation, vel_crossfade, \
+                                                                           step_crossfade, fractional_step, \
+                                                                           trigger, release_half_life, \
+                                                                           articulation, controllers, \
+                                                                           choke_group, start_offset or id")),
                                     }
                                 }
                             }
@@ -560,6 +775,18 @@ mod sample_over_range {
                         match try!(visitor.visit_key()) {
                             Some(Field::Range) => { range = Some(try!(visitor.visit_value())); },
                             Some(Field::Sample) => { sample = Some(try!(visitor.visit_value())); },
+                            Some(Field::Alternates) => { alternates = Some(try!(visitor.visit_value())); },
+                            Some(Field::Alternation) => { alternation = Some(try!(visitor.visit_value())); },
+                            Some(Field::VelCrossfade) => { vel_crossfade = Some(try!(visitor.visit_value())); },
+                            Some(Field::StepCrossfade) => { step_crossfade = Some(try!(visitor.visit_value())); },
+                            Some(Field::FractionalStep) => { fractional_step = Some(try!(visitor.visit_value())); },
+                            Some(Field::Trigger) => { trigger = Some(try!(visitor.visit_value())); },
+                            Some(Field::ReleaseHalfLife) => { release_half_life = Some(try!(visitor.visit_value())); },
+                            Some(Field::Articulation) => { articulation = Some(try!(visitor.visit_value())); },
+                            Some(Field::Controllers) => { controllers = Some(try!(visitor.visit_value())); },
+                            Some(Field::ChokeGroup) => { choke_group = Some(try!(visitor.visit_value())); },
+                            Some(Field::StartOffset) => { start_offset = Some(try!(visitor.visit_value())); },
+                            Some(Field::Id) => { id = Some(try!(visitor.visit_value())); },
                             None => { break; }
                         }
                     }
@@ -574,13 +801,61 @@ mod sample_over_range {
                         None => return Err(serde::de::Error::missing_field("sample")),
                     };
 
+                    // Zones without a group simply play their `sample` on every `note_on`.
+                    let alternates = alternates.unwrap_or_else(Vec::new);
+                    let alternation = alternation.unwrap_or_else(Alternation::round_robin);
+
+                    // Zones without a crossfade hide the zones that they overlap.
+                    let vel_crossfade = vel_crossfade.unwrap_or(None);
+                    let step_crossfade = step_crossfade.unwrap_or(None);
+
+                    // Zones without fractional bounds cover their whole integer `range.step`.
+                    let fractional_step = fractional_step.unwrap_or(None);
+
+                    // Zones without a trigger are played by `note_on`.
+                    let trigger = trigger.unwrap_or(Trigger::Attack);
+                    let release_half_life = release_half_life.unwrap_or(None);
+
+                    // Zones without an articulation are played by every articulation.
+                    let articulation = articulation.unwrap_or(None);
+
+                    // Zones without controllers are played regardless of controller values.
+                    let controllers = controllers.unwrap_or_else(Vec::new);
+
+                    // Zones without a choke group neither choke nor are choked by other notes.
+                    let choke_group = choke_group.unwrap_or(None);
+
+                    // Zones without a start offset always begin from the start of their audio.
+                    let start_offset = start_offset.unwrap_or(None);
+
+                    // Zones without an id are given one when added to a `Map`.
+                    let id = id.unwrap_or(None);
+
                     try!(visitor.end());
 
-                    Ok(SampleOverRange { range: range, sample: sample })
+                    Ok(SampleOverRange {
+                        range: range,
+                        sample: sample,
+                        alternates: alternates,
+                        alternation: alternation,
+                        vel_crossfade: vel_crossfade,
+                        step_crossfade: step_crossfade,
+                        fractional_step: fractional_step,
+                        trigger: trigger,
+                        release_half_life: release_half_life,
+                        articulation: articulation,
+                        controllers: controllers,
+                        choke_group: choke_group,
+                        start_offset: start_offset,
+                        id: id,
+                    })
                 }
             }
 
-            static FIELDS: &'static [&'static str] = &["range", "sample"];
+            static FIELDS: &'static [&'static str] = &["range", "sample", "alternates", "alternation",
+                                                       "vel_crossfade", "step_crossfade", "fractional_step",
+                                                       "trigger", "release_half_life", "articulation",
+                                                       "controllers", "choke_group", "start_offset", "id"];
 
             let visitor = Visitor { t: std::marker::PhantomData };
 
@@ -599,17 +874,17 @@ mod sample_over_range {
         //     fn data(&self) -> &[Self::Frame] { &[] }
         // }
 
-        let sample = map::Sample { base_hz: 440.0.into(), base_vel: 1.0, audio: () };
+        let sample = map::Sample::new(440.0.into(), 1.0, ());
         let range = map::StepVelRange {
             step: map::Range { min: 0, max: 127 },
             vel: map::Range { min: 0.0, max: 1.0 },
         };
 
-        let sample_over_range = SampleOverRange { range: range, sample: sample };
+        let sample_over_range = SampleOverRange::new(range, sample);
         let serialized = serde_json::to_string(&sample_over_range).unwrap();
 
         println!("{}", serialized);
-        assert_eq!("{\"range\":{\"step\":{\"min\":0,\"max\":127},\"vel\":{\"min\":0,\"max\":1}},\"sample\":{\"base_hz\":440,\"base_vel\":1,\"audio\":null}}", serialized);
+        assert_eq!("{\"range\":{\"step\":{\"min\":0,\"max\":127},\"vel\":{\"min\":0,\"max\":1}},\"sample\":{\"base_hz\":440,\"base_vel\":1,\"audio\":null,\"key_tracking\":1,\"one_shot\":false,\"tune_cents\":0,\"gain_db\":0,\"pan\":0,\"sustain_loop\":null},\"alternates\":[],\"alternation\":{\"RoundRobin\":[]},\"vel_crossfade\":null,\"step_crossfade\":null,\"fractional_step\":null,\"trigger\":{\"Attack\":[]},\"release_half_life\":null,\"articulation\":null,\"controllers\":[],\"choke_group\":null,\"start_offset\":null,\"id\":null}", serialized);
         
         let deserialized: SampleOverRange<()> = serde_json::from_str(&serialized).unwrap();
 
@@ -620,16 +895,209 @@ mod sample_over_range {
 }
 
 
-mod step_vel_range {
+mod alternation {
+    use map::Alternation;
     use super::serde;
-    use map::StepVelRange;
 
-    impl serde::Serialize for StepVelRange {
+    // Only the kind of `Alternation` is stored. The position within the group is runtime state
+    // and always begins from the first sample when deserialized.
+    impl serde::Serialize for Alternation {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            match *self {
+                Alternation::RoundRobin { .. } =>
+                    serializer.serialize_unit_variant("Alternation", 0, "RoundRobin"),
+                Alternation::RoundRobinPerStep { .. } =>
+                    serializer.serialize_unit_variant("Alternation", 1, "RoundRobinPerStep"),
+                Alternation::Random { .. } =>
+                    serializer.serialize_unit_variant("Alternation", 2, "Random"),
+            }
+        }
+    }
+
+    impl serde::Deserialize for Alternation {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            enum Variant {
+                RoundRobin,
+                RoundRobinPerStep,
+                Random,
+            }
+
+            impl serde::de::Deserialize for Variant {
+                fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                    where D: serde::Deserializer,
+                {
+                    struct VariantVisitor;
+
+                    impl serde::de::Visitor for VariantVisitor {
+                        type Value = Variant;
+
+                        fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                            where E: serde::de::Error,
+                        {
+                            match value {
+                                "RoundRobin" => Ok(Variant::RoundRobin),
+                                "RoundRobinPerStep" => Ok(Variant::RoundRobinPerStep),
+                                "Random" => Ok(Variant::Random),
+                                _ => Err(serde::de::Error::unknown_field(value)),
+                            }
+                        }
+                    }
+
+                    deserializer.deserialize(VariantVisitor)
+                }
+            }
+
+            struct Visitor;
+
+            impl serde::de::EnumVisitor for Visitor {
+                type Value = Alternation;
+
+                fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                    where V: serde::de::VariantVisitor,
+                {
+                    let alternation = match try!(visitor.visit_variant()) {
+                        Variant::RoundRobin => Alternation::round_robin(),
+                        Variant::RoundRobinPerStep => Alternation::round_robin_per_step(),
+                        Variant::Random => Alternation::random(),
+                    };
+                    try!(visitor.visit_unit());
+                    Ok(alternation)
+                }
+            }
+
+            const VARIANTS: &'static [&'static str] = &["RoundRobin", "RoundRobinPerStep", "Random"];
+
+            deserializer.deserialize_enum("Alternation", VARIANTS, Visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+
+        use rand;
+
+        let mut rng = rand::XorShiftRng::new_unseeded();
+        let mut alternation = Alternation::round_robin_per_step();
+        alternation.next(60, 4, &mut rng);
+        let serialized = serde_json::to_string(&alternation).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"RoundRobinPerStep\":[]}", serialized);
+
+        let deserialized: Alternation = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(Alternation::round_robin_per_step(), deserialized);
+    }
+
+}
+
+
+mod crossfade {
+    use map::Crossfade;
+    use super::serde;
+
+    impl serde::Serialize for Crossfade {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            match *self {
+                Crossfade::Linear => serializer.serialize_unit_variant("Crossfade", 0, "Linear"),
+                Crossfade::EqualPower => serializer.serialize_unit_variant("Crossfade", 1, "EqualPower"),
+            }
+        }
+    }
+
+    impl serde::Deserialize for Crossfade {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            enum Variant {
+                Linear,
+                EqualPower,
+            }
+
+            impl serde::de::Deserialize for Variant {
+                fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                    where D: serde::Deserializer,
+                {
+                    struct VariantVisitor;
+
+                    impl serde::de::Visitor for VariantVisitor {
+                        type Value = Variant;
+
+                        fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                            where E: serde::de::Error,
+                        {
+                            match value {
+                                "Linear" => Ok(Variant::Linear),
+                                "EqualPower" => Ok(Variant::EqualPower),
+                                _ => Err(serde::de::Error::unknown_field(value)),
+                            }
+                        }
+                    }
+
+                    deserializer.deserialize(VariantVisitor)
+                }
+            }
+
+            struct Visitor;
+
+            impl serde::de::EnumVisitor for Visitor {
+                type Value = Crossfade;
+
+                fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                    where V: serde::de::VariantVisitor,
+                {
+                    let crossfade = match try!(visitor.visit_variant()) {
+                        Variant::Linear => Crossfade::Linear,
+                        Variant::EqualPower => Crossfade::EqualPower,
+                    };
+                    try!(visitor.visit_unit());
+                    Ok(crossfade)
+                }
+            }
+
+            const VARIANTS: &'static [&'static str] = &["Linear", "EqualPower"];
+
+            deserializer.deserialize_enum("Crossfade", VARIANTS, Visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+
+        let crossfade = Crossfade::EqualPower;
+        let serialized = serde_json::to_string(&crossfade).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"EqualPower\":[]}", serialized);
+
+        let deserialized: Crossfade = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(crossfade, deserialized);
+    }
+
+}
+
+
+mod sample_loop {
+    use super::serde;
+    use map::Loop;
+
+    impl serde::Serialize for Loop {
         fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
             where S: serde::Serializer,
         {
             struct Visitor<'a> {
-                t: &'a StepVelRange,
+                t: &'a Loop,
                 field_idx: u8,
             }
 
@@ -640,41 +1108,51 @@ mod step_vel_range {
                     match self.field_idx {
                         0 => {
                             self.field_idx += 1;
-                            Ok(Some(try!(serializer.serialize_struct_elt("step", &self.t.step))))
+                            Ok(Some(try!(serializer.serialize_struct_elt("start", &self.t.start))))
                         },
                         1 => {
                             self.field_idx += 1;
-                            Ok(Some(try!(serializer.serialize_struct_elt("vel", &self.t.vel))))
+                            Ok(Some(try!(serializer.serialize_struct_elt("end", &self.t.end))))
+                        },
+                        2 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("mode", &self.t.mode))))
+                        },
+                        3 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("release", &self.t.release))))
                         },
                         _ => Ok(None),
                     }
                 }
 
                 fn len(&self) -> Option<usize> {
-                    Some(2)
+                    Some(4)
                 }
             }
 
-            serializer.serialize_struct("StepVelRange", Visitor { t: self, field_idx: 0 })
+            serializer.serialize_struct("Loop", Visitor { t: self, field_idx: 0 })
         }
     }
 
-    impl serde::Deserialize for StepVelRange {
+    impl serde::Deserialize for Loop {
         fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
             where D: serde::Deserializer,
         {
             struct Visitor;
 
             impl serde::de::Visitor for Visitor {
-                type Value = StepVelRange;
+                type Value = Loop;
 
-                fn visit_map<V>(&mut self, mut visitor: V) -> Result<StepVelRange, V::Error>
+                fn visit_map<V>(&mut self, mut visitor: V) -> Result<Loop, V::Error>
                     where V: serde::de::MapVisitor,
                 {
-                    let mut step = None;
-                    let mut vel = None;
+                    let mut start = None;
+                    let mut end = None;
+                    let mut mode = None;
+                    let mut release = None;
 
-                    enum Field { Step, Vel }
+                    enum Field { Start, End, Mode, Release }
 
                     impl serde::Deserialize for Field {
                         fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
@@ -689,9 +1167,11 @@ mod step_vel_range {
                                     where E: serde::de::Error,
                                 {
                                     match value {
-                                        "step" => Ok(Field::Step),
-                                        "vel" => Ok(Field::Vel),
-                                        _ => Err(serde::de::Error::custom("expected step or vel")),
+                                        "start" => Ok(Field::Start),
+                                        "end" => Ok(Field::End),
+                                        "mode" => Ok(Field::Mode),
+                                        "release" => Ok(Field::Release),
+                                        _ => Err(serde::de::Error::custom("expected start, end, mode or release")),
                                     }
                                 }
                             }
@@ -702,46 +1182,675 @@ mod step_vel_range {
 
                     loop {
                         match try!(visitor.visit_key()) {
-                            Some(Field::Step) => { step = Some(try!(visitor.visit_value())); },
-                            Some(Field::Vel) => { vel = Some(try!(visitor.visit_value())); },
+                            Some(Field::Start) => { start = Some(try!(visitor.visit_value())); },
+                            Some(Field::End) => { end = Some(try!(visitor.visit_value())); },
+                            Some(Field::Mode) => { mode = Some(try!(visitor.visit_value())); },
+                            Some(Field::Release) => { release = Some(try!(visitor.visit_value())); },
                             None => { break; }
                         }
                     }
 
-                    let step = match step {
-                        Some(step) => step,
-                        None => return Err(serde::de::Error::missing_field("step")),
+                    let start = match start {
+                        Some(start) => start,
+                        None => return Err(serde::de::Error::missing_field("start")),
                     };
 
-                    let vel = match vel {
-                        Some(vel) => vel,
-                        None => return Err(serde::de::Error::missing_field("vel")),
+                    let end = match end {
+                        Some(end) => end,
+                        None => return Err(serde::de::Error::missing_field("end")),
+                    };
+
+                    let mode = match mode {
+                        Some(mode) => mode,
+                        None => return Err(serde::de::Error::missing_field("mode")),
+                    };
+
+                    let release = match release {
+                        Some(release) => release,
+                        None => return Err(serde::de::Error::missing_field("release")),
                     };
 
                     try!(visitor.end());
 
-                    Ok(StepVelRange { step: step, vel: vel })
+                    Ok(Loop { start: start, end: end, mode: mode, release: release })
                 }
             }
 
-            static FIELDS: &'static [&'static str] = &["step", "vel"];
+            static FIELDS: &'static [&'static str] = &["start", "end", "mode", "release"];
 
             let visitor = Visitor;
 
-            deserializer.deserialize_struct("StepVelRange", FIELDS, visitor)
+            deserializer.deserialize_struct("Loop", FIELDS, visitor)
         }
     }
 
     #[test]
     fn test() {
         extern crate serde_json;
-        use map;
+        use map::{LoopMode, LoopRelease};
 
-        let range = StepVelRange {
-            step: map::Range { min: 0, max: 127 },
-            vel: map::Range { min: 0.0, max: 1.0 },
+        let sustain_loop = Loop {
+            start: 10,
+            end: 20,
+            mode: LoopMode::PingPong,
+            release: LoopRelease::PlayToEnd,
         };
-        let serialized = serde_json::to_string(&range).unwrap();
+        let serialized = serde_json::to_string(&sustain_loop).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"start\":10,\"end\":20,\"mode\":{\"PingPong\":[]},\"release\":{\"PlayToEnd\":[]}}", serialized);
+
+        let deserialized: Loop = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(sustain_loop, deserialized);
+    }
+
+}
+
+
+mod loop_mode {
+    use map::LoopMode;
+    use super::serde;
+
+    impl serde::Serialize for LoopMode {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            match *self {
+                LoopMode::Forward => serializer.serialize_unit_variant("LoopMode", 0, "Forward"),
+                LoopMode::Backward => serializer.serialize_unit_variant("LoopMode", 1, "Backward"),
+                LoopMode::PingPong => serializer.serialize_unit_variant("LoopMode", 2, "PingPong"),
+            }
+        }
+    }
+
+    impl serde::Deserialize for LoopMode {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            enum Variant {
+                Forward,
+                Backward,
+                PingPong,
+            }
+
+            impl serde::de::Deserialize for Variant {
+                fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                    where D: serde::Deserializer,
+                {
+                    struct VariantVisitor;
+
+                    impl serde::de::Visitor for VariantVisitor {
+                        type Value = Variant;
+
+                        fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                            where E: serde::de::Error,
+                        {
+                            match value {
+                                "Forward" => Ok(Variant::Forward),
+                                "Backward" => Ok(Variant::Backward),
+                                "PingPong" => Ok(Variant::PingPong),
+                                _ => Err(serde::de::Error::unknown_field(value)),
+                            }
+                        }
+                    }
+
+                    deserializer.deserialize(VariantVisitor)
+                }
+            }
+
+            struct Visitor;
+
+            impl serde::de::EnumVisitor for Visitor {
+                type Value = LoopMode;
+
+                fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                    where V: serde::de::VariantVisitor,
+                {
+                    let loop_mode = match try!(visitor.visit_variant()) {
+                        Variant::Forward => LoopMode::Forward,
+                        Variant::Backward => LoopMode::Backward,
+                        Variant::PingPong => LoopMode::PingPong,
+                    };
+                    try!(visitor.visit_unit());
+                    Ok(loop_mode)
+                }
+            }
+
+            const VARIANTS: &'static [&'static str] = &["Forward", "Backward", "PingPong"];
+
+            deserializer.deserialize_enum("LoopMode", VARIANTS, Visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+
+        let loop_mode = LoopMode::Backward;
+        let serialized = serde_json::to_string(&loop_mode).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"Backward\":[]}", serialized);
+
+        let deserialized: LoopMode = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(loop_mode, deserialized);
+    }
+
+}
+
+
+mod loop_release {
+    use map::LoopRelease;
+    use super::serde;
+
+    impl serde::Serialize for LoopRelease {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            match *self {
+                LoopRelease::Continue => serializer.serialize_unit_variant("LoopRelease", 0, "Continue"),
+                LoopRelease::PlayToEnd => serializer.serialize_unit_variant("LoopRelease", 1, "PlayToEnd"),
+            }
+        }
+    }
+
+    impl serde::Deserialize for LoopRelease {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            enum Variant {
+                Continue,
+                PlayToEnd,
+            }
+
+            impl serde::de::Deserialize for Variant {
+                fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                    where D: serde::Deserializer,
+                {
+                    struct VariantVisitor;
+
+                    impl serde::de::Visitor for VariantVisitor {
+                        type Value = Variant;
+
+                        fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                            where E: serde::de::Error,
+                        {
+                            match value {
+                                "Continue" => Ok(Variant::Continue),
+                                "PlayToEnd" => Ok(Variant::PlayToEnd),
+                                _ => Err(serde::de::Error::unknown_field(value)),
+                            }
+                        }
+                    }
+
+                    deserializer.deserialize(VariantVisitor)
+                }
+            }
+
+            struct Visitor;
+
+            impl serde::de::EnumVisitor for Visitor {
+                type Value = LoopRelease;
+
+                fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                    where V: serde::de::VariantVisitor,
+                {
+                    let loop_release = match try!(visitor.visit_variant()) {
+                        Variant::Continue => LoopRelease::Continue,
+                        Variant::PlayToEnd => LoopRelease::PlayToEnd,
+                    };
+                    try!(visitor.visit_unit());
+                    Ok(loop_release)
+                }
+            }
+
+            const VARIANTS: &'static [&'static str] = &["Continue", "PlayToEnd"];
+
+            deserializer.deserialize_enum("LoopRelease", VARIANTS, Visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+
+        let loop_release = LoopRelease::PlayToEnd;
+        let serialized = serde_json::to_string(&loop_release).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"PlayToEnd\":[]}", serialized);
+
+        let deserialized: LoopRelease = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(loop_release, deserialized);
+    }
+
+}
+
+
+mod trigger {
+    use map::Trigger;
+    use super::serde;
+
+    impl serde::Serialize for Trigger {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            match *self {
+                Trigger::Attack => serializer.serialize_unit_variant("Trigger", 0, "Attack"),
+                Trigger::Release => serializer.serialize_unit_variant("Trigger", 1, "Release"),
+            }
+        }
+    }
+
+    impl serde::Deserialize for Trigger {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            enum Variant {
+                Attack,
+                Release,
+            }
+
+            impl serde::de::Deserialize for Variant {
+                fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                    where D: serde::Deserializer,
+                {
+                    struct VariantVisitor;
+
+                    impl serde::de::Visitor for VariantVisitor {
+                        type Value = Variant;
+
+                        fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                            where E: serde::de::Error,
+                        {
+                            match value {
+                                "Attack" => Ok(Variant::Attack),
+                                "Release" => Ok(Variant::Release),
+                                _ => Err(serde::de::Error::unknown_field(value)),
+                            }
+                        }
+                    }
+
+                    deserializer.deserialize(VariantVisitor)
+                }
+            }
+
+            struct Visitor;
+
+            impl serde::de::EnumVisitor for Visitor {
+                type Value = Trigger;
+
+                fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                    where V: serde::de::VariantVisitor,
+                {
+                    let trigger = match try!(visitor.visit_variant()) {
+                        Variant::Attack => Trigger::Attack,
+                        Variant::Release => Trigger::Release,
+                    };
+                    try!(visitor.visit_unit());
+                    Ok(trigger)
+                }
+            }
+
+            const VARIANTS: &'static [&'static str] = &["Attack", "Release"];
+
+            deserializer.deserialize_enum("Trigger", VARIANTS, Visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+
+        let trigger = Trigger::Release;
+        let serialized = serde_json::to_string(&trigger).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"Release\":[]}", serialized);
+
+        let deserialized: Trigger = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(trigger, deserialized);
+    }
+
+}
+
+
+mod controller {
+    use map::Controller;
+    use super::serde;
+
+    impl serde::Serialize for Controller {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            match *self {
+                Controller::Cc(cc) => serializer.serialize_newtype_variant("Controller", 0, "Cc", cc),
+                Controller::Aftertouch => serializer.serialize_unit_variant("Controller", 1, "Aftertouch"),
+                Controller::Param(param) => serializer.serialize_newtype_variant("Controller", 2, "Param", param),
+            }
+        }
+    }
+
+    impl serde::Deserialize for Controller {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            enum Variant {
+                Cc,
+                Aftertouch,
+                Param,
+            }
+
+            impl serde::de::Deserialize for Variant {
+                fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                    where D: serde::Deserializer,
+                {
+                    struct VariantVisitor;
+
+                    impl serde::de::Visitor for VariantVisitor {
+                        type Value = Variant;
+
+                        fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                            where E: serde::de::Error,
+                        {
+                            match value {
+                                "Cc" => Ok(Variant::Cc),
+                                "Aftertouch" => Ok(Variant::Aftertouch),
+                                "Param" => Ok(Variant::Param),
+                                _ => Err(serde::de::Error::unknown_field(value)),
+                            }
+                        }
+                    }
+
+                    deserializer.deserialize(VariantVisitor)
+                }
+            }
+
+            struct Visitor;
+
+            impl serde::de::EnumVisitor for Visitor {
+                type Value = Controller;
+
+                fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                    where V: serde::de::VariantVisitor,
+                {
+                    match try!(visitor.visit_variant()) {
+                        Variant::Cc => {
+                            let cc = try!(visitor.visit_newtype());
+                            Ok(Controller::Cc(cc))
+                        },
+                        Variant::Aftertouch => {
+                            try!(visitor.visit_unit());
+                            Ok(Controller::Aftertouch)
+                        },
+                        Variant::Param => {
+                            let param = try!(visitor.visit_newtype());
+                            Ok(Controller::Param(param))
+                        },
+                    }
+                }
+            }
+
+            const VARIANTS: &'static [&'static str] = &["Cc", "Aftertouch", "Param"];
+
+            deserializer.deserialize_enum("Controller", VARIANTS, Visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+
+        let controller = Controller::Cc(1);
+        let serialized = serde_json::to_string(&controller).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"Cc\":1}", serialized);
+
+        let deserialized: Controller = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(controller, deserialized);
+
+        let controller = Controller::Aftertouch;
+        let serialized = serde_json::to_string(&controller).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"Aftertouch\":[]}", serialized);
+
+        let deserialized: Controller = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(controller, deserialized);
+    }
+
+}
+
+
+mod start_offset {
+    use map::StartOffset;
+    use super::serde;
+
+    impl serde::Serialize for StartOffset {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            match *self {
+                StartOffset::Frames(frames) => serializer.serialize_newtype_variant("StartOffset", 0, "Frames", frames),
+                StartOffset::Ms(ms) => serializer.serialize_newtype_variant("StartOffset", 1, "Ms", ms),
+            }
+        }
+    }
+
+    impl serde::Deserialize for StartOffset {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            enum Variant {
+                Frames,
+                Ms,
+            }
+
+            impl serde::de::Deserialize for Variant {
+                fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                    where D: serde::Deserializer,
+                {
+                    struct VariantVisitor;
+
+                    impl serde::de::Visitor for VariantVisitor {
+                        type Value = Variant;
+
+                        fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                            where E: serde::de::Error,
+                        {
+                            match value {
+                                "Frames" => Ok(Variant::Frames),
+                                "Ms" => Ok(Variant::Ms),
+                                _ => Err(serde::de::Error::unknown_field(value)),
+                            }
+                        }
+                    }
+
+                    deserializer.deserialize(VariantVisitor)
+                }
+            }
+
+            struct Visitor;
+
+            impl serde::de::EnumVisitor for Visitor {
+                type Value = StartOffset;
+
+                fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                    where V: serde::de::VariantVisitor,
+                {
+                    match try!(visitor.visit_variant()) {
+                        Variant::Frames => {
+                            let frames = try!(visitor.visit_newtype());
+                            Ok(StartOffset::Frames(frames))
+                        },
+                        Variant::Ms => {
+                            let ms = try!(visitor.visit_newtype());
+                            Ok(StartOffset::Ms(ms))
+                        },
+                    }
+                }
+            }
+
+            const VARIANTS: &'static [&'static str] = &["Frames", "Ms"];
+
+            deserializer.deserialize_enum("StartOffset", VARIANTS, Visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+
+        let start_offset = StartOffset::Frames(441);
+        let serialized = serde_json::to_string(&start_offset).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"Frames\":441}", serialized);
+
+        let deserialized: StartOffset = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(start_offset, deserialized);
+    }
+
+}
+
+
+mod step_vel_range {
+    use super::serde;
+    use map::StepVelRange;
+
+    impl serde::Serialize for StepVelRange {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            struct Visitor<'a> {
+                t: &'a StepVelRange,
+                field_idx: u8,
+            }
+
+            impl<'a> serde::ser::MapVisitor for Visitor<'a> {
+                fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                    where S: serde::Serializer,
+                {
+                    match self.field_idx {
+                        0 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("step", &self.t.step))))
+                        },
+                        1 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("vel", &self.t.vel))))
+                        },
+                        _ => Ok(None),
+                    }
+                }
+
+                fn len(&self) -> Option<usize> {
+                    Some(2)
+                }
+            }
+
+            serializer.serialize_struct("StepVelRange", Visitor { t: self, field_idx: 0 })
+        }
+    }
+
+    impl serde::Deserialize for StepVelRange {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            struct Visitor;
+
+            impl serde::de::Visitor for Visitor {
+                type Value = StepVelRange;
+
+                fn visit_map<V>(&mut self, mut visitor: V) -> Result<StepVelRange, V::Error>
+                    where V: serde::de::MapVisitor,
+                {
+                    let mut step = None;
+                    let mut vel = None;
+
+                    enum Field { Step, Vel }
+
+                    impl serde::Deserialize for Field {
+                        fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                            where D: serde::de::Deserializer,
+                        {
+                            struct FieldVisitor;
+
+                            impl serde::de::Visitor for FieldVisitor {
+                                type Value = Field;
+
+                                fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                    where E: serde::de::Error,
+                                {
+                                    match value {
+                                        "step" => Ok(Field::Step),
+                                        "vel" => Ok(Field::Vel),
+                                        _ => Err(serde::de::Error::custom("expected step or vel")),
+                                    }
+                                }
+                            }
+
+                            deserializer.deserialize(FieldVisitor)
+                        }
+                    }
+
+                    loop {
+                        match try!(visitor.visit_key()) {
+                            Some(Field::Step) => { step = Some(try!(visitor.visit_value())); },
+                            Some(Field::Vel) => { vel = Some(try!(visitor.visit_value())); },
+                            None => { break; }
+                        }
+                    }
+
+                    let step = match step {
+                        Some(step) => step,
+                        None => return Err(serde::de::Error::missing_field("step")),
+                    };
+
+                    let vel = match vel {
+                        Some(vel) => vel,
+                        None => return Err(serde::de::Error::missing_field("vel")),
+                    };
+
+                    try!(visitor.end());
+
+                    Ok(StepVelRange { step: step, vel: vel })
+                }
+            }
+
+            static FIELDS: &'static [&'static str] = &["step", "vel"];
+
+            let visitor = Visitor;
+
+            deserializer.deserialize_struct("StepVelRange", FIELDS, visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+        use map;
+
+        let range = StepVelRange {
+            step: map::Range { min: 0, max: 127 },
+            vel: map::Range { min: 0.0, max: 1.0 },
+        };
+        let serialized = serde_json::to_string(&range).unwrap();
 
         println!("{}", serialized);
         assert_eq!("{\"step\":{\"min\":0,\"max\":127},\"vel\":{\"min\":0,\"max\":1}}", serialized);
@@ -749,120 +1858,432 @@ mod step_vel_range {
         let deserialized: StepVelRange = serde_json::from_str(&serialized).unwrap();
 
         println!("{:?}", deserialized);
-        assert_eq!(range, deserialized);
+        assert_eq!(range, deserialized);
+    }
+
+}
+
+
+mod map {
+    use super::serde;
+    use map::{self, Controller, Map, DEFAULT_CHOKE_FADE_MS};
+    use std;
+    use time;
+
+    impl<A> serde::Serialize for Map<A>
+        where A: serde::Serialize,
+    {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            struct Visitor<'a, A: 'a> {
+                t: &'a Map<A>,
+                field_idx: u8,
+            }
+
+            impl<'a, A> serde::ser::MapVisitor for Visitor<'a, A>
+                where A: serde::Serialize,
+            {
+                fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                    where S: serde::Serializer,
+                {
+                    match self.field_idx {
+                        0 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("pairs", &self.t.pairs))))
+                        },
+                        1 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("key_switches", &self.t.key_switches))))
+                        },
+                        2 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("choke_fade", &self.t.choke_fade))))
+                        },
+                        3 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("articulation", &self.t.articulation()))))
+                        },
+                        4 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("controllers", self.t.controllers()))))
+                        },
+                        5 => {
+                            self.field_idx += 1;
+                            let next_id = map::private::next_id(self.t);
+                            Ok(Some(try!(serializer.serialize_struct_elt("next_id", &next_id))))
+                        },
+                        _ => Ok(None),
+                    }
+                }
+
+                fn len(&self) -> Option<usize> {
+                    Some(6)
+                }
+            }
+
+            serializer.serialize_struct("Map", Visitor { t: self, field_idx: 0 })
+        }
+    }
+
+    impl<A> serde::Deserialize for Map<A>
+        where A: serde::Deserialize,
+    {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            struct Visitor<A> {
+                t: std::marker::PhantomData<A>,
+            };
+
+            impl<A> serde::de::Visitor for Visitor<A>
+                where A: serde::Deserialize,
+            {
+                type Value = Map<A>;
+
+                fn visit_map<V>(&mut self, mut visitor: V) -> Result<Map<A>, V::Error>
+                    where V: serde::de::MapVisitor,
+                {
+                    let mut pairs = None;
+                    let mut key_switches = None;
+                    let mut choke_fade = None;
+                    let mut articulation = None;
+                    let mut controllers = None;
+                    let mut next_id = None;
+
+                    enum Field {
+                        Pairs,
+                        KeySwitches,
+                        ChokeFade,
+                        Articulation,
+                        Controllers,
+                        NextId,
+                    }
+
+                    impl serde::Deserialize for Field {
+                        fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                            where D: serde::de::Deserializer,
+                        {
+                            struct FieldVisitor;
+
+                            impl serde::de::Visitor for FieldVisitor {
+                                type Value = Field;
+
+                                fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                    where E: serde::de::Error,
+                                {
+                                    match value {
+                                        "pairs" => Ok(Field::Pairs),
+                                        "key_switches" => Ok(Field::KeySwitches),
+                                        "choke_fade" => Ok(Field::ChokeFade),
+                                        "articulation" => Ok(Field::Articulation),
+                                        "controllers" => Ok(Field::Controllers),
+                                        "next_id" => Ok(Field::NextId),
+                                        _ => Err(serde::de::Error::custom("expected pairs, key_switches, \
+                                                                           choke_fade, articulation, \
+                                                                           controllers or next_id")),
+                                    }
+                                }
+                            }
+
+                            deserializer.deserialize(FieldVisitor)
+                        }
+                    }
+
+                    loop {
+                        match try!(visitor.visit_key()) {
+                            Some(Field::Pairs) => { pairs = Some(try!(visitor.visit_value())); },
+                            Some(Field::KeySwitches) => { key_switches = Some(try!(visitor.visit_value())); },
+                            Some(Field::ChokeFade) => { choke_fade = Some(try!(visitor.visit_value())); },
+                            Some(Field::Articulation) => { articulation = Some(try!(visitor.visit_value())); },
+                            Some(Field::Controllers) => { controllers = Some(try!(visitor.visit_value())); },
+                            Some(Field::NextId) => { next_id = Some(try!(visitor.visit_value())); },
+                            None => { break; }
+                        }
+                    }
+
+                    let pairs = match pairs {
+                        Some(pairs) => pairs,
+                        None => return Err(serde::de::Error::missing_field("pairs")),
+                    };
+
+                    // Maps without key switches always play their first articulation.
+                    let key_switches = key_switches.unwrap_or(None);
+                    let choke_fade = choke_fade.unwrap_or(time::Ms(DEFAULT_CHOKE_FADE_MS));
+                    let articulation = articulation.unwrap_or(0);
+                    let controllers: Vec<(Controller, f32)> = controllers.unwrap_or_else(Vec::new);
+
+                    // Maps without a `next_id` give new zones ids following those of their `pairs`.
+                    let next_id = next_id.unwrap_or(0);
+
+                    try!(visitor.end());
+
+                    let mut map = Map::from_pairs(pairs);
+                    map::private::reserve_ids(&mut map, next_id);
+                    map.key_switches = key_switches;
+                    map.choke_fade = choke_fade;
+                    map.set_articulation(articulation);
+                    for (controller, value) in controllers {
+                        map.set_controller(controller, value);
+                    }
+                    Ok(map)
+                }
+            }
+
+            static FIELDS: &'static [&'static str] = &["pairs", "key_switches", "choke_fade",
+                                                       "articulation", "controllers", "next_id"];
+
+            let visitor = Visitor { t: std::marker::PhantomData };
+
+            deserializer.deserialize_struct("Map", FIELDS, visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+
+        let map: Map<()> = Map::empty();
+        let serialized = serde_json::to_string(&map).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"pairs\":[],\"key_switches\":null,\"choke_fade\":10,\"articulation\":0,\"controllers\":[],\"next_id\":0}", serialized);
+        
+        let deserialized: Map<()> = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(map, deserialized);
+
+        // Zone ids survive the round trip, even once a zone has been removed.
+        let range = |min, max| map::StepVelRange {
+            step: map::Range { min: min, max: max },
+            vel: map::Range { min: 0.0, max: 1.0 },
+        };
+        let sample = || map::Sample::new(440.0.into(), 1.0, ());
+        let mut map: Map<()> = Map::empty();
+        let a = map.insert_zone(map::SampleOverRange::new(range(0, 59), sample()));
+        let b = map.insert_zone(map::SampleOverRange::new(range(60, 127), sample()));
+        map.remove(a);
+        let serialized = serde_json::to_string(&map).unwrap();
+        let mut deserialized: Map<()> = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(map, deserialized);
+        assert_eq!(Some(0), deserialized.position(b));
+        assert_eq!(None, deserialized.position(a));
+        let c = deserialized.insert_zone(map::SampleOverRange::new(range(0, 59), sample()));
+        assert!(c != a && c != b);
+    }
+
+}
+
+
+mod velocity_curve {
+    use super::serde;
+    use velocity::Curve;
+
+    impl serde::Serialize for Curve {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            match *self {
+                Curve::Linear => serializer.serialize_unit_variant("Curve", 0, "Linear"),
+                Curve::Exponential(amount) => serializer.serialize_newtype_variant("Curve", 1, "Exponential", amount),
+                Curve::Logarithmic(amount) => serializer.serialize_newtype_variant("Curve", 2, "Logarithmic", amount),
+                Curve::SCurve(amount) => serializer.serialize_newtype_variant("Curve", 3, "SCurve", amount),
+                Curve::Fixed(vel) => serializer.serialize_newtype_variant("Curve", 4, "Fixed", vel),
+                Curve::Table(ref table) => serializer.serialize_newtype_variant("Curve", 5, "Table", table),
+            }
+        }
+    }
+
+    impl serde::Deserialize for Curve {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            enum Variant {
+                Linear,
+                Exponential,
+                Logarithmic,
+                SCurve,
+                Fixed,
+                Table,
+            }
+
+            impl serde::de::Deserialize for Variant {
+                fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                    where D: serde::Deserializer,
+                {
+                    struct VariantVisitor;
+
+                    impl serde::de::Visitor for VariantVisitor {
+                        type Value = Variant;
+
+                        fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                            where E: serde::de::Error,
+                        {
+                            match value {
+                                "Linear" => Ok(Variant::Linear),
+                                "Exponential" => Ok(Variant::Exponential),
+                                "Logarithmic" => Ok(Variant::Logarithmic),
+                                "SCurve" => Ok(Variant::SCurve),
+                                "Fixed" => Ok(Variant::Fixed),
+                                "Table" => Ok(Variant::Table),
+                                _ => Err(serde::de::Error::unknown_field(value)),
+                            }
+                        }
+                    }
+
+                    deserializer.deserialize(VariantVisitor)
+                }
+            }
+
+            struct Visitor;
+
+            impl serde::de::EnumVisitor for Visitor {
+                type Value = Curve;
+
+                fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                    where V: serde::de::VariantVisitor,
+                {
+                    match try!(visitor.visit_variant()) {
+                        Variant::Linear => {
+                            try!(visitor.visit_unit());
+                            Ok(Curve::Linear)
+                        },
+                        Variant::Exponential => {
+                            let amount = try!(visitor.visit_newtype());
+                            Ok(Curve::Exponential(amount))
+                        },
+                        Variant::Logarithmic => {
+                            let amount = try!(visitor.visit_newtype());
+                            Ok(Curve::Logarithmic(amount))
+                        },
+                        Variant::SCurve => {
+                            let amount = try!(visitor.visit_newtype());
+                            Ok(Curve::SCurve(amount))
+                        },
+                        Variant::Fixed => {
+                            let vel = try!(visitor.visit_newtype());
+                            Ok(Curve::Fixed(vel))
+                        },
+                        Variant::Table => {
+                            let table = try!(visitor.visit_newtype());
+                            Ok(Curve::Table(table))
+                        },
+                    }
+                }
+            }
+
+            const VARIANTS: &'static [&'static str] = &["Linear", "Exponential", "Logarithmic",
+                                                        "SCurve", "Fixed", "Table"];
+
+            deserializer.deserialize_enum("Curve", VARIANTS, Visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+
+        let curve = Curve::Table(vec![0.0, 0.75, 1.0]);
+        let serialized = serde_json::to_string(&curve).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"Table\":[0,0.75,1]}", serialized);
+
+        let deserialized: Curve = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(curve, deserialized);
+
+        let curve = Curve::Linear;
+        let serialized = serde_json::to_string(&curve).unwrap();
+
+        println!("{}", serialized);
+        assert_eq!("{\"Linear\":[]}", serialized);
+
+        let deserialized: Curve = serde_json::from_str(&serialized).unwrap();
+
+        println!("{:?}", deserialized);
+        assert_eq!(curve, deserialized);
     }
 
 }
 
 
-mod map {
+mod gain_model {
     use super::serde;
-    use map::Map;
-    use std;
+    use velocity::GainModel;
 
-    impl<A> serde::Serialize for Map<A>
-        where A: serde::Serialize,
-    {
+    impl serde::Serialize for GainModel {
         fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
             where S: serde::Serializer,
         {
-            struct Visitor<'a, A: 'a> {
-                t: &'a Map<A>,
-                field_idx: u8,
-            }
-
-            impl<'a, A> serde::ser::MapVisitor for Visitor<'a, A>
-                where A: serde::Serialize,
-            {
-                fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
-                    where S: serde::Serializer,
-                {
-                    match self.field_idx {
-                        0 => {
-                            self.field_idx += 1;
-                            Ok(Some(try!(serializer.serialize_struct_elt("pairs", &self.t.pairs))))
-                        },
-                        _ => Ok(None),
-                    }
-                }
-
-                fn len(&self) -> Option<usize> {
-                    Some(1)
-                }
+            match *self {
+                GainModel::Legacy =>
+                    serializer.serialize_unit_variant("GainModel", 0, "Legacy"),
+                GainModel::Relative { dynamic_range_db } =>
+                    serializer.serialize_newtype_variant("GainModel", 1, "Relative", dynamic_range_db),
             }
-
-            serializer.serialize_struct("Map", Visitor { t: self, field_idx: 0 })
         }
     }
 
-    impl<A> serde::Deserialize for Map<A>
-        where A: serde::Deserialize,
-    {
+    impl serde::Deserialize for GainModel {
         fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
             where D: serde::Deserializer,
         {
-            struct Visitor<A> {
-                t: std::marker::PhantomData<A>,
-            };
-
-            impl<A> serde::de::Visitor for Visitor<A>
-                where A: serde::Deserialize,
-            {
-                type Value = Map<A>;
+            enum Variant {
+                Legacy,
+                Relative,
+            }
 
-                fn visit_map<V>(&mut self, mut visitor: V) -> Result<Map<A>, V::Error>
-                    where V: serde::de::MapVisitor,
+            impl serde::de::Deserialize for Variant {
+                fn deserialize<D>(deserializer: &mut D) -> Result<Variant, D::Error>
+                    where D: serde::Deserializer,
                 {
-                    let mut pairs = None;
+                    struct VariantVisitor;
 
-                    enum Field { Pairs }
+                    impl serde::de::Visitor for VariantVisitor {
+                        type Value = Variant;
 
-                    impl serde::Deserialize for Field {
-                        fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
-                            where D: serde::de::Deserializer,
+                        fn visit_str<E>(&mut self, value: &str) -> Result<Variant, E>
+                            where E: serde::de::Error,
                         {
-                            struct FieldVisitor;
-
-                            impl serde::de::Visitor for FieldVisitor {
-                                type Value = Field;
-
-                                fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
-                                    where E: serde::de::Error,
-                                {
-                                    match value {
-                                        "pairs" => Ok(Field::Pairs),
-                                        _ => Err(serde::de::Error::custom("expected pairs")),
-                                    }
-                                }
+                            match value {
+                                "Legacy" => Ok(Variant::Legacy),
+                                "Relative" => Ok(Variant::Relative),
+                                _ => Err(serde::de::Error::unknown_field(value)),
                             }
-
-                            deserializer.deserialize(FieldVisitor)
                         }
                     }
 
-                    loop {
-                        match try!(visitor.visit_key()) {
-                            Some(Field::Pairs) => { pairs = Some(try!(visitor.visit_value())); },
-                            None => { break; }
-                        }
-                    }
+                    deserializer.deserialize(VariantVisitor)
+                }
+            }
 
-                    let pairs = match pairs {
-                        Some(pairs) => pairs,
-                        None => return Err(serde::de::Error::missing_field("pairs")),
-                    };
+            struct Visitor;
 
-                    try!(visitor.end());
+            impl serde::de::EnumVisitor for Visitor {
+                type Value = GainModel;
 
-                    Ok(Map { pairs: pairs })
+                fn visit<V>(&mut self, mut visitor: V) -> Result<Self::Value, V::Error>
+                    where V: serde::de::VariantVisitor,
+                {
+                    match try!(visitor.visit_variant()) {
+                        Variant::Legacy => {
+                            try!(visitor.visit_unit());
+                            Ok(GainModel::Legacy)
+                        },
+                        Variant::Relative => {
+                            let dynamic_range_db = try!(visitor.visit_newtype());
+                            Ok(GainModel::Relative { dynamic_range_db: dynamic_range_db })
+                        },
+                    }
                 }
             }
 
-            static FIELDS: &'static [&'static str] = &["pairs"];
-
-            let visitor = Visitor { t: std::marker::PhantomData };
+            const VARIANTS: &'static [&'static str] = &["Legacy", "Relative"];
 
-            deserializer.deserialize_struct("Map", FIELDS, visitor)
+            deserializer.deserialize_enum("GainModel", VARIANTS, Visitor)
         }
     }
 
@@ -870,16 +2291,16 @@ mod map {
     fn test() {
         extern crate serde_json;
 
-        let map: Map<()> = Map::empty();
-        let serialized = serde_json::to_string(&map).unwrap();
+        let gain_model = GainModel::Relative { dynamic_range_db: 40.0 };
+        let serialized = serde_json::to_string(&gain_model).unwrap();
 
         println!("{}", serialized);
-        assert_eq!("{\"pairs\":[]}", serialized);
-        
-        let deserialized: Map<()> = serde_json::from_str(&serialized).unwrap();
+        assert_eq!("{\"Relative\":40}", serialized);
+
+        let deserialized: GainModel = serde_json::from_str(&serialized).unwrap();
 
         println!("{:?}", deserialized);
-        assert_eq!(map, deserialized);
+        assert_eq!(gain_model, deserialized);
     }
 
 }
@@ -891,6 +2312,7 @@ mod sampler {
     use super::serde;
     use sampler::{self, Sampler};
     use std;
+    use velocity::{Curve, GainModel};
 
     impl<M, NFG, A> serde::Serialize for Sampler<M, NFG, A>
         where M: serde::Serialize,
@@ -932,12 +2354,24 @@ mod sampler {
                             let num_voices = self.t.voice_count();
                             Ok(Some(try!(serializer.serialize_struct_elt("voices", num_voices))))
                         },
+                        3 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("selection_curve", &self.t.selection_curve))))
+                        },
+                        4 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("gain_curve", &self.t.gain_curve))))
+                        },
+                        5 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("gain_model", &self.t.gain_model))))
+                        },
                         _ => Ok(None),
                     }
                 }
 
                 fn len(&self) -> Option<usize> {
-                    Some(3)
+                    Some(6)
                 }
             }
 
@@ -974,8 +2408,11 @@ mod sampler {
                     let mut instrument = None;
                     let mut map = None;
                     let mut num_voices = None;
+                    let mut selection_curve = None;
+                    let mut gain_curve = None;
+                    let mut gain_model = None;
 
-                    enum Field { Instrument, Map, Voices }
+                    enum Field { Instrument, Map, Voices, SelectionCurve, GainCurve, GainModel }
 
                     impl serde::Deserialize for Field {
                         fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
@@ -993,7 +2430,12 @@ mod sampler {
                                         "instrument" => Ok(Field::Instrument),
                                         "map" => Ok(Field::Map),
                                         "voices" => Ok(Field::Voices),
-                                        _ => Err(serde::de::Error::custom("expected instrument, map or voices")),
+                                        "selection_curve" => Ok(Field::SelectionCurve),
+                                        "gain_curve" => Ok(Field::GainCurve),
+                                        "gain_model" => Ok(Field::GainModel),
+                                        _ => Err(serde::de::Error::custom("expected instrument, map, voices, \
+                                                                           selection_curve, gain_curve or \
+                                                                           gain_model")),
                                     }
                                 }
                             }
@@ -1007,6 +2449,9 @@ mod sampler {
                             Some(Field::Instrument) => { instrument = Some(try!(visitor.visit_value())); },
                             Some(Field::Map) => { map = Some(try!(visitor.visit_value())); },
                             Some(Field::Voices) => { num_voices = Some(try!(visitor.visit_value())); },
+                            Some(Field::SelectionCurve) => { selection_curve = Some(try!(visitor.visit_value())); },
+                            Some(Field::GainCurve) => { gain_curve = Some(try!(visitor.visit_value())); },
+                            Some(Field::GainModel) => { gain_model = Some(try!(visitor.visit_value())); },
                             None => { break; }
                         }
                     }
@@ -1026,13 +2471,25 @@ mod sampler {
                         None => return Err(serde::de::Error::missing_field("voices")),
                     };
 
+                    // Samplers without velocity curves use the raw `note_on` velocity.
+                    let selection_curve = selection_curve.unwrap_or(Curve::Linear);
+                    let gain_curve = gain_curve.unwrap_or(Curve::Linear);
+
+                    // Samplers without a gain model scale each sample by its `base_vel`.
+                    let gain_model = gain_model.unwrap_or(GainModel::Legacy);
+
                     try!(visitor.end());
 
-                    Ok(sampler::private::new(instrument, map, num_voices))
+                    let mut sampler = sampler::private::new(instrument, map, num_voices);
+                    sampler.selection_curve = selection_curve;
+                    sampler.gain_curve = gain_curve;
+                    sampler.gain_model = gain_model;
+                    Ok(sampler)
                 }
             }
 
-            static FIELDS: &'static [&'static str] = &["instrument", "map", "voices"];
+            static FIELDS: &'static [&'static str] = &["instrument", "map", "voices", "selection_curve",
+                                                       "gain_curve", "gain_model"];
 
             let visitor = Visitor {
                 m: std::marker::PhantomData,
@@ -1063,6 +2520,9 @@ mod sampler {
         assert_eq!(&sampler.instrument, &deserialized.instrument);
         assert_eq!(&sampler.map, &deserialized.map);
         assert_eq!(sampler.voice_count(), deserialized.voice_count());
+        assert_eq!(sampler.selection_curve, deserialized.selection_curve);
+        assert_eq!(sampler.gain_curve, deserialized.gain_curve);
+        assert_eq!(sampler.gain_model, deserialized.gain_model);
     }
 
 }
@@ -1217,3 +2677,153 @@ mod wav_audio {
         assert_eq!(audio, deserialized);
     }
 }
+
+#[cfg(feature="flac")]
+mod flac_audio {
+    extern crate find_folder;
+
+    use audio::flac;
+    use sample;
+    use super::serde;
+    use std;
+
+    impl<F> serde::Serialize for flac::Audio<F> {
+        fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+            where S: serde::Serializer,
+        {
+            struct Visitor<'a, F: 'a> {
+                t: &'a flac::Audio<F>,
+                field_idx: u8,
+            }
+
+            impl<'a, F> serde::ser::MapVisitor for Visitor<'a, F> {
+                fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+                    where S: serde::Serializer,
+                {
+                    match self.field_idx {
+                        0 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("path", &self.t.path))))
+                        },
+                        1 => {
+                            self.field_idx += 1;
+                            Ok(Some(try!(serializer.serialize_struct_elt("sample_hz", &self.t.sample_hz))))
+                        },
+                        _ => Ok(None),
+                    }
+                }
+
+                fn len(&self) -> Option<usize> {
+                    Some(2)
+                }
+            }
+
+            serializer.serialize_struct("Audio", Visitor { t: self, field_idx: 0 })
+        }
+    }
+
+    impl<F> serde::Deserialize for flac::Audio<F>
+        where F: sample::Frame + serde::Deserialize,
+              F::Sample: sample::Duplex<f64> + sample::Duplex<i32>,
+              Box<[F::Sample]>: sample::ToBoxedFrameSlice<F>,
+    {
+        fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+            where D: serde::Deserializer,
+        {
+            struct Visitor<F> {
+                f: std::marker::PhantomData<F>,
+            };
+
+            impl<F> serde::de::Visitor for Visitor<F>
+                where F: sample::Frame + serde::Deserialize,
+                      F::Sample: sample::Duplex<f64> + sample::Duplex<i32>,
+                      Box<[F::Sample]>: sample::ToBoxedFrameSlice<F>,
+            {
+                type Value = flac::Audio<F>;
+
+                fn visit_map<V>(&mut self, mut visitor: V) -> Result<flac::Audio<F>, V::Error>
+                    where V: serde::de::MapVisitor,
+                {
+                    let mut path = None;
+                    let mut sample_hz = None;
+
+                    enum Field { Path, SampleHz }
+
+                    impl serde::Deserialize for Field {
+                        fn deserialize<D>(deserializer: &mut D) -> Result<Field, D::Error>
+                            where D: serde::de::Deserializer,
+                        {
+                            struct FieldVisitor;
+
+                            impl serde::de::Visitor for FieldVisitor {
+                                type Value = Field;
+
+                                fn visit_str<E>(&mut self, value: &str) -> Result<Field, E>
+                                    where E: serde::de::Error,
+                                {
+                                    match value {
+                                        "path" => Ok(Field::Path),
+                                        "sample_hz" => Ok(Field::SampleHz),
+                                        _ => Err(serde::de::Error::custom("expected path or sample_hz")),
+                                    }
+                                }
+                            }
+
+                            deserializer.deserialize(FieldVisitor)
+                        }
+                    }
+
+                    loop {
+                        match try!(visitor.visit_key()) {
+                            Some(Field::Path) => { path = Some(try!(visitor.visit_value())); },
+                            Some(Field::SampleHz) => { sample_hz = Some(try!(visitor.visit_value())); },
+                            None => { break; }
+                        }
+                    }
+
+                    let path: std::path::PathBuf = match path {
+                        Some(path) => path,
+                        None => return Err(serde::de::Error::missing_field("path")),
+                    };
+
+                    let sample_hz = match sample_hz {
+                        Some(sample_hz) => sample_hz,
+                        None => return Err(serde::de::Error::missing_field("sample_hz")),
+                    };
+
+                    try!(visitor.end());
+
+                    flac::Audio::from_file(path, sample_hz).map_err(|e| {
+                        serde::de::Error::custom(std::error::Error::description(&e))
+                    })
+                }
+            }
+
+            static FIELDS: &'static [&'static str] = &["path", "sample_hz"];
+
+            let visitor = Visitor { f: std::marker::PhantomData };
+
+            deserializer.deserialize_struct("Audio", FIELDS, visitor)
+        }
+    }
+
+    #[test]
+    fn test() {
+        extern crate serde_json;
+
+        const RAMP: &'static str = "ramp C4.flac";
+        const SAMPLE_HZ: f64 = 44_100.0;
+
+        let assets = find_folder::Search::ParentsThenKids(5, 5).for_folder("assets").unwrap();
+        let path = assets.join(RAMP);
+        let audio = flac::Audio::<[i32; 2]>::from_file(path, SAMPLE_HZ).unwrap();
+
+        let serialized = serde_json::to_string(&audio).unwrap();
+
+        println!("{}", serialized);
+
+        let deserialized: flac::Audio<[i32; 2]> = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(audio, deserialized);
+    }
+}
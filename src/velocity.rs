@@ -0,0 +1,180 @@
+//! Velocity response curves, used to adapt the feel of the `Sampler` to different keyboards, and
+//! the models by which velocity determines the loudness of each sample.
+//!
+//! Each curve maps a `note_on` velocity within the range `0.0` to `1.0` to a new velocity within
+//! the same range.
+
+use std;
+use Velocity;
+
+
+/// A curve by which the velocity of a `note_on` is shaped.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Curve {
+    /// The velocity is left unchanged.
+    Linear,
+    /// Soft notes are made softer, so that more force is needed to reach the louder layers.
+    ///
+    /// The amount is the steepness of the curve, where `0.0` is linear.
+    Exponential(f32),
+    /// Soft notes are made louder, so that less force is needed to reach the louder layers.
+    ///
+    /// The amount is the steepness of the curve, where `0.0` is linear.
+    Logarithmic(f32),
+    /// Velocities around the middle of the range are spread apart while those at either extreme
+    /// are compressed.
+    ///
+    /// The amount is the steepness of the curve, where `1.0` is linear.
+    SCurve(f32),
+    /// Every note is played at the given velocity, regardless of how hard it was struck.
+    Fixed(Velocity),
+    /// A user-defined curve, given as output velocities evenly spaced over the input range and
+    /// interpolated linearly, i.e. `[0.0, 0.8, 1.0]` maps `0.5` to `0.8`.
+    ///
+    /// An empty table leaves the velocity unchanged.
+    Table(Vec<Velocity>),
+}
+
+
+impl Curve {
+
+    /// Shapes the given velocity.
+    ///
+    /// The velocity is clamped to the range `0.0` to `1.0`, as is the result.
+    pub fn apply(&self, vel: Velocity) -> Velocity {
+        let vel = clamp(vel);
+        let shaped = match *self {
+            Curve::Linear => vel,
+            Curve::Exponential(amount) => {
+                if amount == 0.0 {
+                    vel
+                } else {
+                    ((amount * vel).exp() - 1.0) / (amount.exp() - 1.0)
+                }
+            },
+            Curve::Logarithmic(amount) => {
+                if amount <= 0.0 {
+                    vel
+                } else {
+                    (1.0 + amount * vel).ln() / (1.0 + amount).ln()
+                }
+            },
+            Curve::SCurve(amount) => {
+                if amount <= 0.0 {
+                    vel
+                } else {
+                    let (low, high) = (vel.powf(amount), (1.0 - vel).powf(amount));
+                    low / (low + high)
+                }
+            },
+            Curve::Fixed(fixed) => fixed,
+            Curve::Table(ref table) => match table.len() {
+                0 => vel,
+                1 => table[0],
+                len => {
+                    let pos = vel * (len - 1) as f32;
+                    let idx = std::cmp::min(pos.floor() as usize, len - 2);
+                    let frac = pos - idx as f32;
+                    table[idx] + (table[idx + 1] - table[idx]) * frac
+                },
+            },
+        };
+        clamp(shaped)
+    }
+
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Curve::Linear
+    }
+}
+
+/// The model by which the velocity of a note and the `base_vel` of each of its samples determine
+/// the amplitude at which the sample is played.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GainModel {
+    /// The amplitude is the product of the note's velocity and the sample's `base_vel`.
+    ///
+    /// A sample recorded at velocity `0.5` and triggered at `0.5` is played at a quarter of its
+    /// recorded amplitude.
+    Legacy,
+    /// Velocities are mapped to levels spanning the given dynamic range in decibels, from the
+    /// full range below unity at velocity `0.0` up to unity at velocity `1.0`.
+    ///
+    /// Each sample is played relative to the level of its own `base_vel`, so a sample triggered
+    /// at its `base_vel` is played at its recorded amplitude and loudness stays consistent across
+    /// velocity layers.
+    Relative {
+        dynamic_range_db: f32,
+    },
+}
+
+impl GainModel {
+
+    /// The amplitude given to a note triggered at the given velocity.
+    pub fn note_amp(&self, vel: Velocity) -> f32 {
+        match *self {
+            GainModel::Legacy => vel,
+            GainModel::Relative { dynamic_range_db } => db_to_amp(dynamic_range_db * (vel - 1.0)),
+        }
+    }
+
+    /// The amount by which a sample recorded at the given `base_vel` is scaled when played within
+    /// a note whose amplitude is given by `note_amp`.
+    pub fn sample_amp(&self, base_vel: Velocity) -> f32 {
+        match *self {
+            GainModel::Legacy => base_vel,
+            GainModel::Relative { .. } => 1.0 / self.note_amp(base_vel),
+        }
+    }
+
+}
+
+impl Default for GainModel {
+    fn default() -> Self {
+        GainModel::Legacy
+    }
+}
+
+
+/// Converts the given gain in decibels to a linear amplitude.
+fn db_to_amp(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+/// Clamps the given velocity to the range `0.0` to `1.0`.
+fn clamp(vel: Velocity) -> Velocity {
+    vel.max(0.0).min(1.0)
+}
+
+
+#[test]
+fn test_apply() {
+    assert_eq!(Curve::Linear.apply(0.25), 0.25);
+    assert_eq!(Curve::Linear.apply(1.5), 1.0);
+    assert_eq!(Curve::Linear.apply(-0.5), 0.0);
+    assert!(Curve::Exponential(3.0).apply(0.5) < 0.5);
+    assert_eq!(Curve::Exponential(3.0).apply(1.0), 1.0);
+    assert!(Curve::Logarithmic(9.0).apply(0.5) > 0.5);
+    assert_eq!(Curve::Logarithmic(9.0).apply(0.0), 0.0);
+    assert_eq!(Curve::SCurve(2.0).apply(0.5), 0.5);
+    assert!(Curve::SCurve(2.0).apply(0.25) < 0.25);
+    assert!(Curve::SCurve(2.0).apply(0.75) > 0.75);
+    assert_eq!(Curve::Fixed(0.7).apply(0.1), 0.7);
+    assert_eq!(Curve::Table(vec![0.0, 0.75, 1.0]).apply(0.25), 0.375);
+    assert_eq!(Curve::Table(vec![0.0, 0.75, 1.0]).apply(1.0), 1.0);
+    assert_eq!(Curve::Table(vec![]).apply(0.25), 0.25);
+}
+
+#[test]
+fn test_gain_model() {
+    let legacy = GainModel::Legacy;
+    assert_eq!(legacy.note_amp(0.5) * legacy.sample_amp(0.5), 0.25);
+
+    let relative = GainModel::Relative { dynamic_range_db: 40.0 };
+    assert_eq!(relative.note_amp(1.0), 1.0);
+    assert!((relative.note_amp(0.5) - 0.1).abs() < 1e-6);
+    assert!((relative.note_amp(0.5) * relative.sample_amp(0.5) - 1.0).abs() < 1e-6);
+    assert!((relative.note_amp(1.0) * relative.sample_amp(0.5) - 10.0).abs() < 1e-4);
+}